@@ -0,0 +1,161 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input::ActionState,
+    replay::TickInput,
+    settings::Settings,
+    shop::{Upgrade, Upgrades},
+    tuning::Tuning,
+    MainCharacter, MousePos,
+};
+
+/// Half the opening of the aimed dynamite blast.
+pub const CONE_ANGLE: f32 = 0.6;
+/// How far from its center line the tractor beam grabs blocks.
+pub const BEAM_WIDTH: f32 = 30.0;
+const DASH_GUIDE_LENGTH: f32 = 80.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum AimMode {
+    /// All around the character, the dash follows the movement.
+    #[default]
+    Radial,
+    /// Toward the cursor, the right stick or a dragged touch button.
+    Aimed,
+}
+
+impl AimMode {
+    pub fn toggled(&self) -> AimMode {
+        match self {
+            AimMode::Radial => AimMode::Aimed,
+            AimMode::Aimed => AimMode::Radial,
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AbilityModes {
+    pub dash: AimMode,
+    pub magnet: AimMode,
+    pub dynamite: AimMode,
+}
+
+/// Where the player is aiming this frame and which abilities follow the aim.
+#[derive(Resource, Default)]
+pub struct Aim {
+    /// The right stick or a dragged touch button when there is one, toward the cursor
+    /// otherwise.
+    pub dir: Vec2,
+    pub to_cursor: Vec2,
+    pub dash: bool,
+    pub magnet: bool,
+    pub dynamite: bool,
+}
+
+pub fn update_aim(
+    main_char: Query<&Transform, With<MainCharacter>>,
+    actions: Res<ActionState>,
+    mouse_pos: Res<MousePos>,
+    settings: Res<Settings>,
+    upgrades: Res<Upgrades>,
+    mut aim: ResMut<Aim>,
+) {
+    let pos = if let Ok(mtr) = main_char.get_single() {
+        mtr.translation.truncate()
+    } else {
+        return;
+    };
+    let modes = &settings.ability_modes;
+    let aimed = |mode: AimMode, upgrade| mode == AimMode::Aimed && upgrades.has(upgrade);
+    aim.to_cursor = (mouse_pos.world - pos).normalize_or_zero();
+    aim.dir = if actions.aim.length() > 0.3 {
+        actions.aim.normalize()
+    } else {
+        aim.to_cursor
+    };
+    aim.dash = aimed(modes.dash, Upgrade::Dash);
+    aim.magnet = aimed(modes.magnet, Upgrade::Magnet);
+    aim.dynamite = aimed(modes.dynamite, Upgrade::Dynamite);
+}
+
+/// Whether a block at `delta` from the character is caught in the blast cone.
+pub fn in_cone(delta: Vec2, aim: Vec2, radius: f32) -> bool {
+    let mag = delta.length();
+    mag > 20.0 && mag < radius && delta.angle_between(aim).abs() < CONE_ANGLE
+}
+
+/// Whether a block at `delta` from the character is caught in the tractor beam.
+pub fn in_beam(delta: Vec2, aim: Vec2, length: f32) -> bool {
+    let along = delta.dot(aim);
+    let across = (delta - aim * along).length();
+    along > 20.0 && along < length && across < BEAM_WIDTH
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum AimGuide {
+    Line,
+    ConeLeft,
+    ConeRight,
+}
+
+pub fn spawn_aim_guides(commands: &mut Commands) {
+    for guide in [AimGuide::Line, AimGuide::ConeLeft, AimGuide::ConeRight] {
+        commands
+            .spawn(SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgba(1.0, 1.0, 1.0, 0.15),
+                    custom_size: Some(Vec2::ONE),
+                    ..default()
+                },
+                visibility: Visibility { is_visible: false },
+                ..default()
+            })
+            .insert(guide);
+    }
+}
+
+pub fn aim_guides(
+    main_char: Query<&Transform, (With<MainCharacter>, Without<AimGuide>)>,
+    mut guides: Query<(&AimGuide, &mut Transform, &mut Visibility)>,
+    input: Res<TickInput>,
+    upgrades: Res<Upgrades>,
+    tuning: Res<Tuning>,
+) {
+    let mtr = if let Ok(mtr) = main_char.get_single() {
+        mtr
+    } else {
+        return;
+    };
+    let pos = mtr.translation.truncate();
+    for (guide, mut tr, mut visibility) in guides.iter_mut() {
+        let (dir, length, visible) = match guide {
+            AimGuide::Line => {
+                let length = if input.aimed_magnet {
+                    upgrades.attract_radius(&tuning) * 2.0
+                } else {
+                    DASH_GUIDE_LENGTH
+                };
+                (input.aim, length, input.aimed_magnet || input.aimed_dash)
+            }
+            AimGuide::ConeLeft | AimGuide::ConeRight => {
+                let angle = if *guide == AimGuide::ConeLeft {
+                    CONE_ANGLE
+                } else {
+                    -CONE_ANGLE
+                };
+                let dir = Vec2::from_angle(angle).rotate(input.aim);
+                (
+                    dir,
+                    upgrades.explode_radius(&tuning) * 1.5,
+                    input.aimed_dynamite,
+                )
+            }
+        };
+        visibility.is_visible = visible && input.aim != Vec2::ZERO;
+        tr.translation = (pos + dir * length * 0.5).extend(mtr.translation.z - 1.0);
+        tr.rotation = Quat::from_rotation_z(dir.y.atan2(dir.x));
+        tr.scale = Vec3::new(length, 2.0, 1.0);
+    }
+}
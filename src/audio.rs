@@ -0,0 +1,518 @@
+use std::{f32::consts::FRAC_PI_4, io::Cursor};
+
+use bevy::{
+    audio::{play_queued_audio_system, AudioSink, Decodable},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+use rodio::{
+    source::{ChannelVolume, Empty},
+    Decoder, Source,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    console::Cheats, dialogue::DialogueTrigger, particles, settings::Settings, Block, GameAssets,
+    Info, Progress,
+};
+
+/// Loudness of a music stem, of a stinger note and of a full force hit, before the
+/// volume settings.
+const MUSIC_LEVEL: f32 = 0.2;
+const STINGER_LEVEL: f32 = 0.4;
+const HIT_LEVEL: f32 = 0.3;
+/// How much of its full volume a stem gains or loses per second.
+const FADE_SPEED: f32 = 0.5;
+/// A score gain of this much of the threshold counts toward a combo, the next one has to
+/// follow within the window.
+const COMBO_STEP: f32 = 0.02;
+const COMBO_WINDOW: f32 = 1.0;
+/// Combos this long play the pulse stem at full volume.
+const FULL_COMBO: u32 = 5;
+/// The rush stem comes in over the last part of the golden apple timer.
+const URGENT_TIME_LEFT: f32 = 0.25;
+/// Hits this close together are heard as one.
+const MERGE_WINDOW: f32 = 0.05;
+/// How long `hit.ogg` plays, a voice is free again after that.
+const HIT_LENGTH: f32 = 0.4;
+const MAX_VOICES: usize = 8;
+/// A block of this mass hits at the pitch of the recording.
+const REFERENCE_MASS: f32 = 1600.0;
+const LOWEST_PITCH: f32 = 0.6;
+const HIGHEST_PITCH: f32 = 1.6;
+
+/// Stinger notes, as seconds from the start and pitch of the hit sound.
+const COMPLETE_STINGER: &[(f32, f32)] = &[(0.0, 1.0), (0.12, 1.26), (0.24, 1.5)];
+const APPLE_STINGER: &[(f32, f32)] = &[(0.0, 1.0), (0.1, 1.26), (0.2, 1.5), (0.3, 2.0)];
+const UNLOCK_STINGER: &[(f32, f32)] = &[(0.0, 1.5), (0.15, 2.0)];
+
+/// The volume buses, from 0 to 1. Music and sound effects go through master.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Volumes {
+    pub master: f32,
+    pub music: f32,
+    pub sfx: f32,
+}
+
+impl Default for Volumes {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            music: 1.0,
+            sfx: 1.0,
+        }
+    }
+}
+
+impl Volumes {
+    pub fn music(&self) -> f32 {
+        self.master * self.music
+    }
+
+    pub fn sfx(&self) -> f32 {
+        self.master * self.sfx
+    }
+}
+
+/// Music, stingers and impact sounds, the simulation doesn't need any of it.
+pub struct GameAudioPlugin;
+
+impl Plugin for GameAudioPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Soundtrack>()
+            .init_resource::<Intensity>()
+            .init_resource::<Stingers>()
+            .init_resource::<Impacts>()
+            .init_non_send_resource::<AudioOutput<ImpactSound>>()
+            .add_asset::<ImpactSound>()
+            .init_resource::<Audio<ImpactSound>>()
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                play_queued_audio_system::<ImpactSound>,
+            )
+            .add_system(start_music)
+            .add_system(music_intensity)
+            .add_system(music_layers.after(start_music).after(music_intensity))
+            .add_system(queue_stingers)
+            .add_system(play_stingers.after(queue_stingers).after(start_music))
+            .add_system(gather_impacts)
+            .add_system(play_impacts.after(gather_impacts).after(start_music));
+    }
+}
+
+/// The layers of the music, each fades in with its own part of the intensity.
+#[derive(Clone, Copy)]
+enum Stem {
+    Calm,
+    Drive,
+    Pulse,
+    Rush,
+}
+
+impl Stem {
+    const ALL: [Stem; 4] = [Stem::Calm, Stem::Drive, Stem::Pulse, Stem::Rush];
+
+    /// There are two loops, the upper stems play them an octave up at double speed.
+    fn source(self, my_assets: &GameAssets) -> Handle<AudioSource> {
+        match self {
+            Stem::Calm | Stem::Rush => my_assets.lo.clone(),
+            Stem::Drive | Stem::Pulse => my_assets.hi.clone(),
+        }
+    }
+
+    fn speed(self) -> f32 {
+        match self {
+            Stem::Calm | Stem::Drive => 1.0,
+            Stem::Pulse | Stem::Rush => 2.0,
+        }
+    }
+
+    fn level(self, intensity: &Intensity) -> f32 {
+        match self {
+            Stem::Calm => 1.0 - 0.5 * intensity.progress.max(intensity.urgency),
+            Stem::Drive => ((intensity.progress - 0.2) / 0.6).clamp(0.0, 1.0),
+            Stem::Pulse => (intensity.combo as f32 / FULL_COMBO as f32).min(1.0),
+            Stem::Rush => intensity.urgency,
+        }
+    }
+}
+
+/// How much is going on in the level, it drives the music stems.
+#[derive(Resource, Default)]
+pub struct Intensity {
+    /// `sum / point_threshold`, up to 1.
+    pub progress: f32,
+    /// Score gains in a row, each within `COMBO_WINDOW` of the last.
+    pub combo: u32,
+    /// From 0 to 1 as the golden apple timer runs out.
+    pub urgency: f32,
+    /// Progress at the last gain, the next one counts from there.
+    banked: f32,
+    since_gain: f32,
+}
+
+/// The stem sinks in the order of `Stem::ALL`, with their current volume.
+#[derive(Resource, Default)]
+pub struct Soundtrack {
+    stems: Vec<(Handle<AudioSink>, f32)>,
+    /// Nothing plays before this, browsers only allow sound after the player did something.
+    started: bool,
+}
+
+/// A hit voice, panned between the speakers and pitched by what was hit. Stingers are
+/// played with it too.
+#[derive(TypeUuid)]
+#[uuid = "5e0c7a3d-92f1-4b8e-a6d4-3f18b0c2e971"]
+pub struct ImpactSound {
+    source: AudioSource,
+    /// From -1 on the left to 1 on the right.
+    pan: f32,
+    pitch: f32,
+}
+
+impl Decodable for ImpactSound {
+    type DecoderItem = i16;
+    type Decoder = Box<dyn Source<Item = i16> + Send>;
+
+    /// Silence when the sound can't be decoded, the voice ends right away.
+    fn decoder(&self) -> Self::Decoder {
+        let decoder = match Decoder::new(Cursor::new(self.source.clone())) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                warn!("hit.ogg can't be decoded, {}", e);
+                return Box::new(Empty::new());
+            }
+        };
+        // equal power, a hit in the middle is as loud as one on the side
+        let angle = (self.pan + 1.0) * FRAC_PI_4;
+        Box::new(ChannelVolume::new(
+            decoder.speed(self.pitch),
+            vec![angle.cos(), angle.sin()],
+        ))
+    }
+}
+
+/// Notes waiting to be played, as the time they're due and their pitch, and the ones
+/// still playing, as the time they end.
+#[derive(Resource, Default)]
+struct Stingers {
+    notes: Vec<(f32, f32)>,
+    playing: Vec<(f32, Handle<AudioSink>)>,
+    was_complete: bool,
+}
+
+/// Hits of the current merge window, pan and pitch are weighted by force.
+struct PendingHit {
+    since: f32,
+    force: f32,
+    pan: f32,
+    pitch: f32,
+}
+
+/// The hit being merged and the voices still playing, as the time they end.
+///
+/// The sinks are freed once their handle is dropped, so each voice keeps it until it ends.
+#[derive(Resource, Default)]
+struct Impacts {
+    pending: Option<PendingHit>,
+    voices: Vec<(f32, Handle<AudioSink>)>,
+}
+
+/// Small blocks ring higher than big ones, walls thud lowest.
+fn pitch(block: &Block) -> f32 {
+    if block.density == 0.0 {
+        return LOWEST_PITCH;
+    }
+    let mass = block.density * block.base_size.x * block.base_size.y;
+    (REFERENCE_MASS / mass)
+        .powf(0.25)
+        .clamp(LOWEST_PITCH, HIGHEST_PITCH)
+}
+
+fn start_music(
+    mut soundtrack: ResMut<Soundtrack>,
+    my_assets: Option<Res<GameAssets>>,
+    audio: Res<Audio>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+) {
+    // the assets are there once loading is done
+    let my_assets = match my_assets {
+        Some(my_assets) if !soundtrack.started => my_assets,
+        _ => return,
+    };
+    if cfg!(target_arch = "wasm32")
+        && keyboard_input.get_just_pressed().next().is_none()
+        && mouse_input.get_just_pressed().next().is_none()
+        && touches.iter_just_pressed().next().is_none()
+    {
+        return;
+    }
+    // all at once, so the loops stay in time with each other
+    soundtrack.stems = Stem::ALL
+        .iter()
+        .map(|stem| {
+            let sink = audio.play_with_settings(
+                stem.source(&my_assets),
+                PlaybackSettings {
+                    repeat: true,
+                    volume: 0.0,
+                    speed: stem.speed(),
+                },
+            );
+            (audio_sinks.get_handle(sink), 0.0)
+        })
+        .collect();
+    soundtrack.started = true;
+}
+
+fn music_intensity(
+    block_query: Query<(&Block, &Transform)>,
+    mut info: ResMut<Info>,
+    prog: Res<Progress>,
+    time: Res<Time>,
+    mut intensity: ResMut<Intensity>,
+) {
+    if block_query.iter().next().is_none() || prog.level_complete {
+        *intensity = Intensity::default();
+        return;
+    }
+    let sum: f32 = block_query
+        .iter()
+        .map(|(block, tr)| block.rel(tr.translation))
+        .sum();
+    let progress = (sum / info.get_level(&prog).point_threshold).min(1.0);
+    if progress >= intensity.banked + COMBO_STEP {
+        intensity.combo += 1;
+        intensity.banked = progress;
+        intensity.since_gain = 0.0;
+    } else {
+        intensity.since_gain += time.delta_seconds();
+        if intensity.since_gain > COMBO_WINDOW {
+            intensity.combo = 0;
+        }
+        intensity.banked = intensity.banked.min(progress);
+    }
+    intensity.progress = progress;
+    // no rush once the golden apple is lost
+    let left = 1.0 - prog.level_timer.percent();
+    intensity.urgency = if prog.level_timer.finished() {
+        0.0
+    } else {
+        (1.0 - left / URGENT_TIME_LEFT).clamp(0.0, 1.0)
+    };
+}
+
+/// Fades every stem toward its level for the current intensity.
+fn music_layers(
+    mut soundtrack: ResMut<Soundtrack>,
+    intensity: Res<Intensity>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    let volume = MUSIC_LEVEL * settings.volumes.music();
+    let step = FADE_SPEED * time.delta_seconds();
+    for (stem, (sink, level)) in Stem::ALL.iter().zip(soundtrack.stems.iter_mut()) {
+        *level += (stem.level(&intensity) - *level).clamp(-step, step);
+        if let Some(sink) = audio_sinks.get(sink) {
+            sink.set_volume(*level * volume);
+        }
+    }
+}
+
+/// Level complete, a golden apple won and a new ability each get their stinger.
+fn queue_stingers(
+    prog: Res<Progress>,
+    mut triggers: EventReader<DialogueTrigger>,
+    mut stingers: ResMut<Stingers>,
+    time: Res<Time>,
+    cheats: Res<Cheats>,
+) {
+    let mut notes = Vec::new();
+    if prog.level_complete && !stingers.was_complete {
+        if prog.level_timer.finished() || cheats.active() {
+            notes.extend_from_slice(COMPLETE_STINGER);
+        } else {
+            notes.extend_from_slice(APPLE_STINGER);
+        }
+    }
+    stingers.was_complete = prog.level_complete;
+    for trigger in triggers.iter() {
+        if let DialogueTrigger::Unlock(upgrade) = trigger {
+            if upgrade.action().is_some() {
+                notes.extend_from_slice(UNLOCK_STINGER);
+            }
+        }
+    }
+    let now = time.elapsed_seconds();
+    stingers
+        .notes
+        .extend(notes.into_iter().map(|(at, pitch)| (now + at, pitch)));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn play_stingers(
+    my_assets: Option<Res<GameAssets>>,
+    sources: Res<Assets<AudioSource>>,
+    mut sounds: ResMut<Assets<ImpactSound>>,
+    audio: Res<Audio<ImpactSound>>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    time: Res<Time>,
+    mut stingers: ResMut<Stingers>,
+    soundtrack: Res<Soundtrack>,
+    settings: Res<Settings>,
+) {
+    let now = time.elapsed_seconds();
+    let stingers = &mut *stingers;
+    stingers.playing.retain(|(end, _)| *end > now);
+    let source = match my_assets.and_then(|a| sources.get(&a.hit)) {
+        Some(source) if soundtrack.started => source,
+        _ => {
+            stingers.notes.clear();
+            return;
+        }
+    };
+    let volume = STINGER_LEVEL * settings.volumes.music();
+    let playing = &mut stingers.playing;
+    stingers.notes.retain(|&(at, pitch)| {
+        if at > now {
+            return true;
+        }
+        if volume > 0.0 {
+            let sink = play_hit(
+                &mut sounds,
+                &audio,
+                &audio_sinks,
+                source,
+                0.0,
+                pitch,
+                volume,
+            );
+            playing.push((now + HIT_LENGTH / pitch, sink));
+        }
+        false
+    });
+}
+
+/// Adds the contacts of this frame to the pending hit.
+fn gather_impacts(
+    mut impact_events: EventReader<ContactForceEvent>,
+    bodies: Query<(&Transform, Option<&Block>)>,
+    camera: Query<(&Transform, &OrthographicProjection), With<Camera>>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+    mut impacts: ResMut<Impacts>,
+) {
+    let (cam_tr, projection) = match camera.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let half_width = windows
+        .get_primary()
+        .map_or(1.0, |w| w.width() * 0.5 * projection.scale);
+    for ev in impact_events.iter() {
+        if particles::strength(ev.max_force_magnitude) <= 0.0 {
+            continue;
+        }
+        let hit = bodies.iter_many([ev.collider1, ev.collider2]);
+        let (mut x, mut count, mut tone) = (0.0, 0.0, None::<f32>);
+        for (tr, block) in hit {
+            x += tr.translation.x;
+            count += 1.0;
+            if let Some(block) = block {
+                // the lighter block is the one that rings
+                tone = Some(tone.map_or(pitch(block), |t| t.max(pitch(block))));
+            }
+        }
+        if count == 0.0 {
+            continue;
+        }
+        let pan = ((x / count - cam_tr.translation.x) / half_width).clamp(-1.0, 1.0);
+        let force = ev.max_force_magnitude;
+        let pending = impacts.pending.get_or_insert(PendingHit {
+            since: time.elapsed_seconds(),
+            force: 0.0,
+            pan: 0.0,
+            pitch: 0.0,
+        });
+        pending.force += force;
+        pending.pan += pan * force;
+        pending.pitch += tone.unwrap_or(1.0) * force;
+    }
+}
+
+/// Plays the pending hit once its window is over, louder for every hit merged into it.
+#[allow(clippy::too_many_arguments)]
+fn play_impacts(
+    my_assets: Option<Res<GameAssets>>,
+    sources: Res<Assets<AudioSource>>,
+    mut sounds: ResMut<Assets<ImpactSound>>,
+    audio: Res<Audio<ImpactSound>>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    time: Res<Time>,
+    mut impacts: ResMut<Impacts>,
+    soundtrack: Res<Soundtrack>,
+    settings: Res<Settings>,
+) {
+    let now = time.elapsed_seconds();
+    impacts.voices.retain(|(end, _)| *end > now);
+    let hit = match impacts.pending.take() {
+        Some(hit) if now - hit.since >= MERGE_WINDOW => hit,
+        pending => {
+            impacts.pending = pending;
+            return;
+        }
+    };
+    let source = match my_assets.and_then(|a| sources.get(&a.hit)) {
+        Some(source) if soundtrack.started && impacts.voices.len() < MAX_VOICES => source,
+        _ => return,
+    };
+    // the hits merged into one are louder, up to a hit as strong as the biggest
+    let volume = HIT_LEVEL * particles::strength(hit.force) * settings.volumes.sfx();
+    if volume <= 0.0 {
+        return;
+    }
+    let pitch = hit.pitch / hit.force * rand::thread_rng().gen_range(0.95..1.05);
+    let sink = play_hit(
+        &mut sounds,
+        &audio,
+        &audio_sinks,
+        source,
+        hit.pan / hit.force,
+        pitch,
+        volume,
+    );
+    // a lower pitch plays longer
+    impacts.voices.push((now + HIT_LENGTH / pitch, sink));
+}
+
+/// Returns a strong handle to the sink, it's kept until the sound is over.
+fn play_hit(
+    sounds: &mut Assets<ImpactSound>,
+    audio: &Audio<ImpactSound>,
+    audio_sinks: &Assets<AudioSink>,
+    source: &AudioSource,
+    pan: f32,
+    pitch: f32,
+    volume: f32,
+) -> Handle<AudioSink> {
+    let sound = sounds.add(ImpactSound {
+        source: source.clone(),
+        pan,
+        pitch,
+    });
+    audio_sinks.get_handle(audio.play_with_settings(
+        sound,
+        PlaybackSettings {
+            repeat: false,
+            volume,
+            ..default()
+        },
+    ))
+}
@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+
+use crate::{
+    aim::Aim,
+    input::{Action, ActionState},
+    shop::{Upgrade, Upgrades},
+    sim::harness::Sim,
+    tick::TICK,
+    tuning::Tuning,
+    Block, Info, MainCharacter, Progress,
+};
+
+/// Levels played by `--bot` when no count is given.
+const DEFAULT_LEVELS: u32 = 20;
+/// Layouts tried for every level, the same ones on every run.
+const SEEDS_PER_LEVEL: u64 = 3;
+/// How long the bot keeps trying past the level timer, in multiples of it.
+const OVERTIME: f32 = 3.0;
+/// How far behind a block the bot lines up before pushing it.
+const LINE_UP: f32 = 50.0;
+/// Closer than this and a lined up block gets dashed into.
+const DASH_RANGE: f32 = 200.0;
+
+/// What the bot managed on a level.
+pub struct BotRun {
+    pub level: u32,
+    pub seed: u64,
+    pub blocks: usize,
+    pub threshold: f32,
+    /// The level timer, in seconds.
+    pub duration: f32,
+    /// Seconds until the threshold was reached, none if it never was.
+    pub finish_time: Option<f32>,
+    pub peak_score: f32,
+}
+
+struct Seen {
+    pos: Vec2,
+    base: Vec2,
+    /// How much more the block can score, 1 when it's on its base.
+    remaining: f32,
+}
+
+/// The blocks in reach of an ability that still have points to give.
+fn around(blocks: &[Seen], pos: Vec2, radius: f32) -> impl Iterator<Item = &Seen> {
+    blocks.iter().filter(move |seen| {
+        let distance = seen.pos.distance(pos);
+        distance > 20.0 && distance < radius && seen.remaining > 0.3
+    })
+}
+
+/// Picks the inputs for the next tick from where the character and the blocks are.
+fn decide(sim: &mut Sim, upgrades: &Upgrades) -> (ActionState, Aim) {
+    let mut actions = ActionState::default();
+    let mut aim = Aim::default();
+    let world = sim.world();
+    let tuning = world.resource::<Tuning>().clone();
+    let mut main_char = world.query_filtered::<&Transform, With<MainCharacter>>();
+    let pos = match main_char.get_single(world) {
+        Ok(tr) => tr.translation.truncate(),
+        Err(_) => return (actions, aim),
+    };
+    let mut blocks = world.query::<(&Block, &Transform)>();
+    let blocks: Vec<Seen> = blocks
+        .iter(world)
+        .filter(|(block, _)| block.density > 0.0)
+        .map(|(block, tr)| Seen {
+            pos: tr.translation.truncate(),
+            base: block.base_pos.truncate(),
+            remaining: 1.0 - block.rel(tr.translation),
+        })
+        .collect();
+
+    // the most points left to take, nearby blocks first
+    let target = blocks.iter().max_by(|a, b| {
+        let value = |seen: &Seen| seen.remaining / (1.0 + seen.pos.distance(pos) / 200.0);
+        value(a).total_cmp(&value(b))
+    });
+    let target = match target {
+        Some(target) => target,
+        None => return (actions, aim),
+    };
+
+    // a block is pushed further off its base, one still on it away from the character
+    let to_block = target.pos - pos;
+    let mut push = (target.pos - target.base).normalize_or_zero();
+    if push == Vec2::ZERO {
+        push = to_block.normalize_or_zero();
+    }
+    let lined_up = to_block.normalize_or_zero().dot(push) > 0.8;
+    actions.movement = if lined_up {
+        to_block.normalize_or_zero()
+    } else {
+        (target.pos - push * LINE_UP - pos).normalize_or_zero()
+    };
+    aim.dir = to_block.normalize_or_zero();
+    aim.dash = true;
+
+    if lined_up && to_block.length() < DASH_RANGE && upgrades.has(Upgrade::Dash) {
+        actions.press(Action::Dash);
+    }
+    let in_blast = around(&blocks, pos, upgrades.explode_radius(&tuning)).count();
+    if upgrades.has(Upgrade::Dynamite) && in_blast >= 3 {
+        actions.press(Action::Dynamite);
+    }
+    // the magnet only helps when it drags blocks further from their bases
+    let dragged = around(&blocks, pos, upgrades.attract_radius(&tuning))
+        .filter(|seen| (pos - seen.pos).dot(seen.pos - seen.base) > 0.0)
+        .count();
+    if upgrades.has(Upgrade::Magnet) && dragged >= 2 {
+        actions.press(Action::Magnet);
+    }
+    (actions, aim)
+}
+
+/// Plays a level until the threshold is reached or well past its timer.
+pub fn play_level(level: u32, seed: u64, unlocks: &[Upgrade]) -> BotRun {
+    let mut sim = Sim::new();
+    sim.unlock(unlocks);
+    sim.start_level(level, seed);
+    let upgrades = sim.world().resource::<Upgrades>().clone();
+    let info = sim.world().resource::<Info>();
+    let layout = info
+        .levels
+        .iter()
+        .find(|l| l.id == level)
+        .expect("the level was generated when loaded");
+    let mut run = BotRun {
+        level,
+        seed,
+        blocks: layout.blocks.iter().filter(|b| b.density > 0.0).count(),
+        threshold: layout.point_threshold,
+        duration: layout.duration.as_secs_f32(),
+        finish_time: None,
+        peak_score: 0.0,
+    };
+
+    let max_ticks = (run.duration * OVERTIME / TICK) as u32;
+    for tick in 1..=max_ticks {
+        let (actions, aim) = decide(&mut sim, &upgrades);
+        *sim.actions() = actions;
+        *sim.aim() = aim;
+        sim.run_ticks(1);
+        run.peak_score = run.peak_score.max(sim.score());
+        if sim.world().resource::<Progress>().level_complete {
+            run.finish_time = Some(tick as f32 * TICK);
+            break;
+        }
+    }
+    run
+}
+
+/// `--bot`, or `--bot <levels>`, on the command line.
+pub fn levels_arg() -> Option<u32> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--bot");
+    args.next()?;
+    Some(
+        args.next()
+            .and_then(|levels| levels.parse().ok())
+            .unwrap_or(DEFAULT_LEVELS),
+    )
+}
+
+/// Plays the first levels with every ability and prints how it went, to tune the
+/// generated thresholds and timers.
+pub fn report(levels: u32) {
+    let unlocks = [Upgrade::Dash, Upgrade::Magnet, Upgrade::Dynamite];
+    println!("level    seed  blocks  threshold  timer  finished  peak");
+    for level in 1..=levels {
+        for i in 0..SEEDS_PER_LEVEL {
+            let run = play_level(level, level as u64 * 1000 + i, &unlocks);
+            let finished = run
+                .finish_time
+                .map_or("no".to_string(), |time| format!("{:.1}s", time));
+            println!(
+                "{:>5}  {:>6}  {:>6}  {:>9.1}  {:>4.0}s  {:>8}  {:>4.1}",
+                run.level,
+                run.seed,
+                run.blocks,
+                run.threshold,
+                run.duration,
+                finished,
+                run.peak_score
+            );
+        }
+    }
+}
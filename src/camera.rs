@@ -0,0 +1,104 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{particles, settings::Settings, AbilityUsed};
+
+/// Zoom of the camera at rest.
+pub const CAMERA_SCALE: f32 = 0.666;
+/// Shake at full trauma, in pixels and radians.
+const MAX_OFFSET: f32 = 12.0;
+const MAX_ANGLE: f32 = 0.03;
+/// Trauma and punch lost per second.
+const TRAUMA_DECAY: f32 = 1.5;
+const PUNCH_DECAY: f32 = 6.0;
+/// How much closer a full punch zooms in.
+const PUNCH_ZOOM: f32 = 0.08;
+/// Hits stronger than this shake the camera, see `particles::strength`.
+const SHAKE_STRENGTH: f32 = 0.8;
+
+/// Shakes and punches the camera when something big happens.
+pub struct CameraShakePlugin;
+
+impl Plugin for CameraShakePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraShake>()
+            .add_system(add_trauma)
+            .add_system(shake_camera.after(add_trauma));
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct CameraShake {
+    /// From 0 to 1, the shake grows with its square so small knocks stay small.
+    pub trauma: f32,
+    /// From 0 to 1, a quick zoom in.
+    pub punch: f32,
+}
+
+impl CameraShake {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+}
+
+fn add_trauma(
+    mut shake: ResMut<CameraShake>,
+    mut abilities: EventReader<AbilityUsed>,
+    mut impact_events: EventReader<ContactForceEvent>,
+) {
+    for ability in abilities.iter() {
+        match ability {
+            AbilityUsed::Dash { .. } => shake.add_trauma(0.2),
+            AbilityUsed::Dynamite { .. } => {
+                shake.add_trauma(0.6);
+                shake.punch = 1.0;
+            }
+            AbilityUsed::Magnet { .. } => (),
+        }
+    }
+    let strongest = impact_events
+        .iter()
+        .map(|ev| particles::strength(ev.max_force_magnitude))
+        .fold(0.0, f32::max);
+    if strongest > SHAKE_STRENGTH {
+        shake.add_trauma(strongest - SHAKE_STRENGTH);
+    }
+}
+
+/// From window coordinates, with the origin at the bottom left, to the world seen by the
+/// camera at rest. The shake and the punch only move the view, through them the aim would
+/// shake along.
+pub fn window_to_world(window: &Window, position: Vec2) -> Vec2 {
+    let center = Vec2::new(window.width(), window.height()) * 0.5;
+    (position - center) * CAMERA_SCALE
+}
+
+fn shake_camera(
+    mut camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+    mut shake: ResMut<CameraShake>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    shake.trauma = (shake.trauma - TRAUMA_DECAY * dt).max(0.0);
+    shake.punch = (shake.punch - PUNCH_DECAY * dt).max(0.0);
+    let (mut tr, mut projection) = match camera.get_single_mut() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    if settings.reduce_motion {
+        tr.translation.x = 0.0;
+        tr.translation.y = 0.0;
+        tr.rotation = Quat::IDENTITY;
+        projection.scale = CAMERA_SCALE;
+        return;
+    }
+    // a few sines that don't line up, smoother than picking a new offset every frame
+    let t = time.elapsed_seconds() * 30.0;
+    let noise = |seed: f32| ((t + seed).sin() + 0.5 * (t * 1.7 + seed * 2.3).sin()) / 1.5;
+    let amount = shake.trauma * shake.trauma;
+    tr.translation.x = MAX_OFFSET * amount * noise(0.0);
+    tr.translation.y = MAX_OFFSET * amount * noise(10.0);
+    tr.rotation = Quat::from_rotation_z(MAX_ANGLE * amount * noise(20.0));
+    projection.scale = CAMERA_SCALE * (1.0 - PUNCH_ZOOM * shake.punch);
+}
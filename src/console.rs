@@ -0,0 +1,350 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::{
+    ghost::GhostRun, input::ActionState, menu::MenuNav, pause, place_block, replay::Recorder,
+    tick::GameClock, Block, GameState, Info, MousePos, Progress,
+};
+
+/// Lines of output kept on screen.
+const OUTPUT_LINES: usize = 12;
+
+const COMMANDS: [(&str, &str); 8] = [
+    ("level", "level <n>, jump to a level"),
+    ("apples", "apples <n>, set the golden apples"),
+    ("seed", "seed <n>, regenerate the current level"),
+    ("spawn", "spawn block|heavy|wall, at the cursor"),
+    ("god", "stop the level timer"),
+    ("infinite", "abilities without upgrades or cooldowns"),
+    ("time", "time <scale>, slow down or speed up the game"),
+    ("help", "list the commands"),
+];
+
+/// Playtesting shortcuts, they change how a level plays. They're all off again once
+/// the level is left.
+#[derive(Resource, Default)]
+pub struct Cheats {
+    /// The level timer doesn't run.
+    pub god: bool,
+    /// Every ability, without buying it and without waiting for it.
+    pub infinite: bool,
+    /// A cheat changed the level, finishing it doesn't set a best time or win an apple.
+    pub used: bool,
+    /// The level was jumped to or reseeded, the attempt that starts over doesn't count.
+    jumped: bool,
+}
+
+impl Cheats {
+    pub fn active(&self) -> bool {
+        self.god || self.infinite || self.used
+    }
+
+    /// The run can't be replayed, raced against or counted anymore.
+    fn disqualify(&mut self, recorder: &mut Recorder, ghost_run: &mut GhostRun) {
+        self.used = true;
+        recorder.0 = None;
+        ghost_run.done = true;
+    }
+}
+
+pub fn reset_cheats(mut cheats: ResMut<Cheats>, mut clock: ResMut<GameClock>) {
+    // a jump or a reseed leaves the level to start it over, that attempt is cheated too
+    *cheats = Cheats {
+        used: cheats.jumped,
+        ..default()
+    };
+    clock.speed = 1.0;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SpawnKind {
+    Block,
+    Heavy,
+    Wall,
+}
+
+pub enum ConsoleCommand {
+    Level(u32),
+    Apples(i32),
+    Seed(u64),
+    Spawn(SpawnKind),
+    God,
+    Infinite,
+    Time(f32),
+}
+
+fn number<T: std::str::FromStr>(arg: &str) -> Result<T, String> {
+    arg.parse().map_err(|_| format!("{} is not a number", arg))
+}
+
+impl ConsoleCommand {
+    fn parse(line: &str) -> Result<ConsoleCommand, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let mut arg = || {
+            words
+                .next()
+                .ok_or_else(|| format!("{} needs an argument", name))
+        };
+        Ok(match name {
+            "level" => ConsoleCommand::Level(number(arg()?)?),
+            "apples" => ConsoleCommand::Apples(number(arg()?)?),
+            "seed" => ConsoleCommand::Seed(number(arg()?)?),
+            "spawn" => ConsoleCommand::Spawn(match arg()? {
+                "block" => SpawnKind::Block,
+                "heavy" => SpawnKind::Heavy,
+                "wall" => SpawnKind::Wall,
+                other => return Err(format!("can't spawn a {}", other)),
+            }),
+            "god" => ConsoleCommand::God,
+            "infinite" => ConsoleCommand::Infinite,
+            "time" => {
+                let scale: f32 = number(arg()?)?;
+                if !(0.05..=8.0).contains(&scale) {
+                    return Err("the time scale goes from 0.05 to 8".to_string());
+                }
+                ConsoleCommand::Time(scale)
+            }
+            other => return Err(format!("unknown command {}, try help", other)),
+        })
+    }
+}
+
+/// The dev console, toggled with the key left of 1.
+#[derive(Resource, Default)]
+pub struct Console {
+    open: bool,
+    line: String,
+    history: Vec<String>,
+    /// Where Up and Down are in the history, none while typing a new line.
+    browsing: Option<usize>,
+    output: Vec<String>,
+}
+
+impl Console {
+    fn print(&mut self, text: impl Into<String>) {
+        self.output.push(text.into());
+        let extra = self.output.len().saturating_sub(OUTPUT_LINES);
+        self.output.drain(..extra);
+    }
+
+    /// Completes the command name being typed, or lists the candidates.
+    fn complete(&mut self) {
+        if self.line.contains(' ') {
+            return;
+        }
+        let matches: Vec<&str> = COMMANDS
+            .iter()
+            .map(|(name, _)| *name)
+            .filter(|name| name.starts_with(self.line.as_str()))
+            .collect();
+        match matches.as_slice() {
+            [] => (),
+            [name] => self.line = format!("{} ", name),
+            names => {
+                let names = names.join(" ");
+                self.print(names);
+            }
+        }
+    }
+
+    fn browse(&mut self, back: bool) {
+        if self.history.is_empty() {
+            return;
+        }
+        let last = self.history.len() - 1;
+        self.browsing = match (self.browsing, back) {
+            (None, true) => Some(last),
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i < last => Some(i + 1),
+            _ => None,
+        };
+        self.line = self
+            .browsing
+            .map_or(String::new(), |i| self.history[i].clone());
+    }
+}
+
+/// The keys are for the console, not the character or the menus. Runs after the actions
+/// are read, before anything uses them.
+pub fn block_input(
+    console: Res<Console>,
+    mut actions: ResMut<ActionState>,
+    mut nav: ResMut<MenuNav>,
+) {
+    if console.open {
+        *actions = ActionState::default();
+        nav.cancel();
+    }
+}
+
+pub fn console_window(
+    mut egui_context: ResMut<EguiContext>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut console: ResMut<Console>,
+    mut commands: EventWriter<ConsoleCommand>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Grave) {
+        console.open = !console.open;
+        console.line.clear();
+    }
+    if !console.open {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        console.browse(true);
+    } else if keyboard_input.just_pressed(KeyCode::Down) {
+        console.browse(false);
+    } else if keyboard_input.just_pressed(KeyCode::Tab) {
+        console.complete();
+    }
+
+    let mut submitted = None;
+    egui::Window::new("Console")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, egui::Vec2::splat(0.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            let console = &mut *console;
+            for line in console.output.iter() {
+                ui.monospace(line);
+            }
+            console.line.retain(|c| c != '`' && c != '\t');
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut console.line)
+                    .font(egui::TextStyle::Monospace)
+                    .lock_focus(true),
+            );
+            if response.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+                submitted = Some(std::mem::take(&mut console.line));
+            }
+            response.request_focus();
+        });
+
+    if let Some(line) = submitted {
+        let line = line.trim().to_string();
+        if line.is_empty() {
+            return;
+        }
+        console.print(format!("> {}", line));
+        console.history.push(line.clone());
+        console.browsing = None;
+        if line == "help" {
+            for (name, help) in COMMANDS {
+                console.print(format!("{:<9}{}", name, help));
+            }
+            return;
+        }
+        match ConsoleCommand::parse(&line) {
+            Ok(command) => commands.send(command),
+            Err(e) => console.print(e),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn run_commands(
+    mut commands: Commands,
+    mut events: EventReader<ConsoleCommand>,
+    mut console: ResMut<Console>,
+    mut cheats: ResMut<Cheats>,
+    mut prog: ResMut<Progress>,
+    mut info: ResMut<Info>,
+    mut state: ResMut<State<GameState>>,
+    mut clock: ResMut<GameClock>,
+    mouse_pos: Res<MousePos>,
+    mut recorder: ResMut<Recorder>,
+    mut ghost_run: ResMut<GhostRun>,
+) {
+    let in_level = matches!(
+        state.current(),
+        GameState::Gameplay | GameState::Paused | GameState::Dialogue | GameState::Failed
+    );
+    for command in events.iter() {
+        match command {
+            ConsoleCommand::Level(level) => {
+                if !in_level {
+                    console.print("not in a level");
+                    continue;
+                }
+                cheats.disqualify(&mut recorder, &mut ghost_run);
+                cheats.jumped = true;
+                prog.current_level = (*level).max(1);
+                let duration = info.get_level(&prog).duration;
+                prog.level_timer.set_duration(duration);
+                pause::restart_level(&mut prog, &mut state);
+                console.print(format!("jumping to level {}", prog.current_level));
+            }
+            ConsoleCommand::Apples(apples) => {
+                prog.golden_apples = *apples;
+                console.print(format!("{} golden apples", apples));
+            }
+            ConsoleCommand::Seed(seed) => {
+                if !in_level {
+                    console.print("not in a level");
+                    continue;
+                }
+                cheats.disqualify(&mut recorder, &mut ghost_run);
+                cheats.jumped = true;
+                let level = prog.current_level;
+                info.seeds.insert(level, *seed);
+                info.levels.retain(|l| l.id != level);
+                let duration = info.get_level(&prog).duration;
+                prog.level_timer.set_duration(duration);
+                pause::restart_level(&mut prog, &mut state);
+                console.print(format!("level {} with seed {}", level, seed));
+            }
+            ConsoleCommand::Spawn(kind) => {
+                if !in_level {
+                    console.print("not in a level");
+                    continue;
+                }
+                let block = match kind {
+                    SpawnKind::Block => Block::default(),
+                    SpawnKind::Heavy => Block {
+                        density: 3.0,
+                        color: Color::MAROON,
+                        base_color: Color::MAROON * 0.5,
+                        ..default()
+                    },
+                    SpawnKind::Wall => Block {
+                        density: 0.0,
+                        color: Color::GRAY,
+                        base_color: Color::GRAY,
+                        base_size: Vec2::new(100.0, 20.0),
+                        ..default()
+                    },
+                };
+                place_block(&mut commands, block.mov(mouse_pos.world));
+                cheats.disqualify(&mut recorder, &mut ghost_run);
+                console.print(format!("spawned a {:?}", kind));
+            }
+            ConsoleCommand::God => {
+                cheats.god = !cheats.god;
+                if cheats.god {
+                    cheats.disqualify(&mut recorder, &mut ghost_run);
+                }
+                console.print(format!("god mode {}", on_off(cheats.god)));
+            }
+            ConsoleCommand::Infinite => {
+                cheats.infinite = !cheats.infinite;
+                if cheats.infinite {
+                    cheats.disqualify(&mut recorder, &mut ghost_run);
+                }
+                console.print(format!("infinite abilities {}", on_off(cheats.infinite)));
+            }
+            ConsoleCommand::Time(scale) => {
+                clock.speed = *scale;
+                console.print(format!("time at {}x", scale));
+            }
+        }
+    }
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
@@ -0,0 +1,165 @@
+use bevy::{
+    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+use bevy_egui::{egui, EguiContext, EguiSettings};
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    console::{self, Console, ConsoleCommand},
+    input, menu,
+    shop::Upgrades,
+    touch,
+    tuning::{self, Tuning, TuningInspector},
+    Block, MainCharacter,
+};
+
+/// The console, the tuning inspector and this overlay. They're for playtesting, so
+/// release builds leave them out and players can't cheat into their save.
+pub struct DevToolsPlugin;
+
+impl Plugin for DevToolsPlugin {
+    fn build(&self, app: &mut App) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+        app.init_resource::<TuningInspector>()
+            .init_resource::<DebugOverlay>()
+            .init_resource::<Console>()
+            .add_event::<ConsoleCommand>()
+            .add_plugin(RapierDebugRenderPlugin {
+                enabled: false,
+                ..default()
+            })
+            .add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_system(tuning::tuning_inspector)
+            .add_system(toggle_debug)
+            .add_system(debug_stats)
+            .add_system(debug_world)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                console::block_input
+                    .after(input::update_actions)
+                    .after(touch::touch_controls)
+                    .after(menu::update_menu_nav),
+            )
+            .add_system(console::console_window)
+            .add_system(console::run_commands.after(console::console_window));
+    }
+}
+
+/// What's behind the physics and the score, toggled with F3.
+#[derive(Resource, Default)]
+pub struct DebugOverlay {
+    pub open: bool,
+}
+
+pub fn toggle_debug(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut overlay: ResMut<DebugOverlay>,
+    mut debug_render: ResMut<DebugRenderContext>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        overlay.open = !overlay.open;
+        debug_render.enabled = overlay.open;
+    }
+}
+
+/// Frame times and how many things are alive.
+pub fn debug_stats(
+    mut egui_context: ResMut<EguiContext>,
+    overlay: Res<DebugOverlay>,
+    diagnostics: Res<Diagnostics>,
+    entities: Query<Entity>,
+    colliders: Query<(), With<Collider>>,
+    blocks: Query<(), With<Block>>,
+) {
+    if !overlay.open {
+        return;
+    }
+    let average = |id| diagnostics.get(id).and_then(|d| d.average()).unwrap_or(0.0);
+    egui::Window::new("DebugStats")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::Vec2::splat(0.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(format!(
+                "{:.0} fps, {:.1} ms",
+                average(FrameTimeDiagnosticsPlugin::FPS),
+                average(FrameTimeDiagnosticsPlugin::FRAME_TIME) * 1000.0
+            ));
+            ui.label(format!(
+                "{} entities, {} colliders, {} blocks",
+                entities.iter().count(),
+                colliders.iter().count(),
+                blocks.iter().count()
+            ));
+        });
+}
+
+/// Labels every block with its score and offset, and rings the ability reach.
+#[allow(clippy::too_many_arguments)]
+pub fn debug_world(
+    mut egui_context: ResMut<EguiContext>,
+    egui_settings: Res<EguiSettings>,
+    overlay: Res<DebugOverlay>,
+    windows: Res<Windows>,
+    camera: Query<(&Camera, &GlobalTransform)>,
+    main_char: Query<&Transform, With<MainCharacter>>,
+    blocks: Query<(&Block, &Transform)>,
+    upgrades: Res<Upgrades>,
+    tuning: Res<Tuning>,
+) {
+    if !overlay.open {
+        return;
+    }
+    let (window, (camera, camera_tr)) = match (windows.get_primary(), camera.get_single()) {
+        (Some(window), Ok(camera)) => (window, camera),
+        _ => return,
+    };
+    let scale = egui_settings.scale_factor as f32;
+    let to_egui = |pos: Vec3| {
+        camera
+            .world_to_viewport(camera_tr, pos)
+            .map(|p| egui::pos2(p.x / scale, (window.height() - p.y) / scale))
+    };
+    let painter = egui_context.ctx_mut().layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("DebugOverlay"),
+    ));
+    let stroke = egui::Stroke::new(1.0, egui::Color32::YELLOW);
+
+    for (block, tr) in blocks.iter() {
+        if block.density == 0.0 {
+            continue;
+        }
+        if let (Some(base), Some(pos)) = (to_egui(block.base_pos), to_egui(tr.translation)) {
+            let offset = tr.translation - block.base_pos;
+            painter.line_segment([base, pos], stroke);
+            painter.text(
+                pos,
+                egui::Align2::CENTER_CENTER,
+                format!(
+                    "{:.2}\n{:.0},{:.0}",
+                    block.rel(tr.translation),
+                    offset.x,
+                    offset.y
+                ),
+                egui::FontId::monospace(6.0),
+                egui::Color32::WHITE,
+            );
+        }
+    }
+
+    if let Ok(mtr) = main_char.get_single() {
+        let center = mtr.translation;
+        for (radius, color) in [
+            (upgrades.attract_radius(&tuning), egui::Color32::LIGHT_BLUE),
+            (upgrades.explode_radius(&tuning), egui::Color32::LIGHT_RED),
+        ] {
+            if let (Some(c), Some(edge)) = (to_egui(center), to_egui(center + Vec3::X * radius)) {
+                painter.circle_stroke(c, c.distance(edge), egui::Stroke::new(1.0, color));
+            }
+        }
+    }
+}
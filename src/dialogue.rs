@@ -0,0 +1,226 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_egui::{egui, EguiContext};
+use serde::Deserialize;
+
+use crate::{
+    input::{Action, ActionState},
+    menu::MenuNav,
+    shop::Upgrade,
+    GameAssets, GameState, Progress,
+};
+
+/// How many characters of a line show up each second.
+const TYPING_SPEED: f32 = 40.0;
+
+/// What makes a story beat play.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+pub enum DialogueTrigger {
+    /// Right after starting a new game.
+    Intro,
+    /// When a level with this id starts.
+    Level(u32),
+    /// When the first level of an upgrade is bought.
+    Unlock(Upgrade),
+    /// When the level timer runs out, the golden apple is lost.
+    TimerFail,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct DialogueLine {
+    pub speaker: String,
+    /// Frame of `sheet.png` shown next to the line.
+    pub portrait: usize,
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+pub struct StoryBeat {
+    pub trigger: DialogueTrigger,
+    /// Beats only play the first time they're triggered in a session, unless this is off.
+    #[serde(default = "default_once")]
+    pub once: bool,
+    pub lines: Vec<DialogueLine>,
+}
+
+fn default_once() -> bool {
+    true
+}
+
+/// All the writing, loaded from `assets/story.dialogue.ron`.
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "5b8e4b6e-8d0c-4f5e-9a51-2f6c0e0d7a31"]
+pub struct DialogueScript {
+    pub beats: Vec<StoryBeat>,
+}
+
+#[derive(Default)]
+pub struct DialogueLoader;
+
+impl AssetLoader for DialogueLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let script = ron::de::from_bytes::<DialogueScript>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(script));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["dialogue.ron"]
+    }
+}
+
+/// The lines waiting to be shown, the first one is on screen.
+#[derive(Resource, Default)]
+pub struct Dialogue {
+    queue: VecDeque<DialogueLine>,
+    /// Characters of the current line shown so far.
+    typed: f32,
+    played: HashSet<DialogueTrigger>,
+}
+
+impl Dialogue {
+    pub fn play(&mut self, script: &DialogueScript, trigger: DialogueTrigger) {
+        if self.queue.is_empty() {
+            self.typed = 0.0;
+        }
+        for beat in script.beats.iter().filter(|b| b.trigger == trigger) {
+            if beat.once && !self.played.insert(trigger) {
+                continue;
+            }
+            self.queue.extend(beat.lines.iter().cloned());
+        }
+    }
+
+    fn line_length(&self) -> usize {
+        self.queue.front().map_or(0, |l| l.text.chars().count())
+    }
+
+    fn advance(&mut self) {
+        let length = self.line_length();
+        if (self.typed as usize) < length {
+            self.typed = length as f32;
+        } else {
+            self.queue.pop_front();
+            self.typed = 0.0;
+        }
+    }
+
+    fn skip(&mut self) {
+        self.queue.clear();
+        self.typed = 0.0;
+    }
+}
+
+pub fn queue_dialogue(
+    mut triggers: EventReader<DialogueTrigger>,
+    my_assets: Res<GameAssets>,
+    scripts: Res<Assets<DialogueScript>>,
+    mut dialogue: ResMut<Dialogue>,
+) {
+    if let Some(script) = scripts.get(&my_assets.dialogue) {
+        for trigger in triggers.iter() {
+            dialogue.play(script, *trigger);
+        }
+    }
+}
+
+pub fn start_intro(
+    my_assets: Res<GameAssets>,
+    scripts: Res<Assets<DialogueScript>>,
+    mut dialogue: ResMut<Dialogue>,
+) {
+    // a new game, the story is told again from the start
+    dialogue.played.clear();
+    if let Some(script) = scripts.get(&my_assets.dialogue) {
+        dialogue.play(script, DialogueTrigger::Intro);
+    }
+}
+
+pub fn level_dialogue(prog: Res<Progress>, mut triggers: EventWriter<DialogueTrigger>) {
+    triggers.send(DialogueTrigger::Level(prog.current_level));
+}
+
+/// Stops the gameplay while there's something to read.
+pub fn start_dialogue(dialogue: Res<Dialogue>, mut state: ResMut<State<GameState>>) {
+    if !dialogue.queue.is_empty() {
+        state.push(GameState::Dialogue).ok();
+    }
+}
+
+/// Types the line on screen, nothing is typed ahead while there's none.
+pub fn type_dialogue(mut dialogue: ResMut<Dialogue>, time: Res<Time>) {
+    let length = dialogue.line_length() as f32;
+    dialogue.typed = (dialogue.typed + TYPING_SPEED * time.delta_seconds()).min(length);
+}
+
+pub fn dialogue_box(
+    mut egui_context: ResMut<EguiContext>,
+    my_assets: Res<GameAssets>,
+    atlases: Res<Assets<TextureAtlas>>,
+    mut dialogue: ResMut<Dialogue>,
+    mut actions: ResMut<ActionState>,
+    mut state: ResMut<State<GameState>>,
+    mut nav: ResMut<MenuNav>,
+) {
+    let atlas = atlases.get(&my_assets.lolle);
+    let texture = atlas.map(|atlas| egui_context.add_image(atlas.texture.clone_weak()));
+    let mut next = actions.consume(Action::Dash);
+    let mut skip = actions.consume(Action::Pause);
+    if let Some(line) = dialogue.queue.front() {
+        egui::Window::new("Dialogue")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -20.0))
+            .show(egui_context.ctx_mut(), |ui| {
+                ui.horizontal(|ui| {
+                    if let (Some(atlas), Some(texture)) = (atlas, texture) {
+                        let size = atlas.size;
+                        let rect = atlas.textures[line.portrait % atlas.len()];
+                        let uv = egui::Rect::from_min_max(
+                            egui::pos2(rect.min.x / size.x, rect.min.y / size.y),
+                            egui::pos2(rect.max.x / size.x, rect.max.y / size.y),
+                        );
+                        ui.add(egui::Image::new(texture, egui::vec2(64.0, 64.0)).uv(uv));
+                    }
+                    ui.vertical(|ui| {
+                        ui.label(
+                            egui::RichText::new(&line.speaker)
+                                .strong()
+                                .color(egui::Color32::GOLD),
+                        );
+                        let typed: String =
+                            line.text.chars().take(dialogue.typed as usize).collect();
+                        ui.label(typed);
+                    });
+                });
+                ui.horizontal(|ui| {
+                    next |= nav.button(ui, true, "Next");
+                    skip |= nav.button(ui, true, "Skip");
+                });
+            });
+    }
+    if skip {
+        dialogue.skip();
+    } else if next {
+        dialogue.advance();
+    }
+    if dialogue.queue.is_empty() {
+        if *state.current() == GameState::Intro {
+            state.set(GameState::LevelLoading).ok();
+        } else {
+            state.pop().ok();
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{GameAssets, Info};
+
+/// Colors of a level.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Theme {
+    /// Range the block hue is picked from, walls and targets are offset from it.
+    pub hue: (f32, f32),
+    /// Lightness of the grey background.
+    pub background: f32,
+}
+
+/// Blocks that make a level harder, on top of the walls.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HazardMix {
+    /// Chance of a block being heavy, it takes more pushing.
+    pub heavy: f32,
+    pub heavy_density: f32,
+}
+
+impl Default for HazardMix {
+    fn default() -> Self {
+        Self {
+            heavy: 0.0,
+            heavy_density: 3.0,
+        }
+    }
+}
+
+/// How the levels from `from` up to the next band are generated.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DifficultyBand {
+    /// First level of the band.
+    pub from: u32,
+    /// Blocks placed in a quarter of the arena, the other three are mirrored.
+    pub magnitude: u32,
+    /// Grows the magnitude with the level number, when that's more than `magnitude`.
+    #[serde(default)]
+    pub magnitude_per_level: u32,
+    pub wall_probability: f32,
+    /// Smallest and biggest side of a block.
+    pub block_size: (u32, u32),
+    pub wall_length: (u32, u32),
+    pub wall_width: (u32, u32),
+    /// Part of the blocks that have to be pushed all the way to finish.
+    pub threshold: f32,
+    pub time_per_block: f32,
+    pub time_per_wall: f32,
+    pub theme: Theme,
+    #[serde(default)]
+    pub hazards: HazardMix,
+}
+
+impl DifficultyBand {
+    pub fn magnitude(&self, level: u32) -> u32 {
+        self.magnitude.max(level * self.magnitude_per_level)
+    }
+
+    /// The ranges of a single band, the table checks them all when it loads.
+    fn validate(&self) -> Result<(), String> {
+        let sizes = [
+            ("block_size", self.block_size),
+            ("wall_length", self.wall_length),
+            ("wall_width", self.wall_width),
+        ];
+        for (name, (min, max)) in sizes {
+            if min < 5 || min > max {
+                return Err(format!("{} goes from 5 up, smallest first", name));
+            }
+        }
+        // anything as long as a wall is built as one
+        if self.block_size.1 >= self.wall_length.0 {
+            return Err("blocks have to be shorter than the walls".to_string());
+        }
+        let chances = [
+            ("wall_probability", self.wall_probability),
+            ("hazards.heavy", self.hazards.heavy),
+        ];
+        for (name, chance) in chances {
+            if !(0.0..=1.0).contains(&chance) {
+                return Err(format!("{} is a chance, from 0 to 1", name));
+            }
+        }
+        let (low, high) = self.theme.hue;
+        if !(0.0..=360.0).contains(&low) || !(low..=360.0).contains(&high) {
+            return Err("the hue goes from 0 to 360, smallest first".to_string());
+        }
+        if self.magnitude == 0 && self.magnitude_per_level == 0 {
+            return Err("a level needs at least one block".to_string());
+        }
+        if self.threshold < 0.0 || self.time_per_block < 0.0 || self.time_per_wall < 0.0 {
+            return Err("the threshold and times can't be negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// The difficulty curve, loaded from `assets/levels.difficulty.ron`.
+#[derive(Clone, Serialize, Deserialize, TypeUuid)]
+#[uuid = "0f6f3c1a-52a4-4d8e-b3c4-7e2b9a1d6c58"]
+pub struct DifficultyTable {
+    /// Sorted by their first level.
+    pub bands: Vec<DifficultyBand>,
+}
+
+impl Default for DifficultyTable {
+    /// The table shipped with the game, for when the asset isn't loaded, like headless.
+    fn default() -> Self {
+        ron::from_str(include_str!("../assets/levels.difficulty.ron"))
+            .expect("the shipped difficulty table is valid")
+    }
+}
+
+impl DifficultyTable {
+    /// Level generation panics or goes wrong on these, so they're caught when the table loads.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bands.is_empty() {
+            return Err("the difficulty table has no bands".to_string());
+        }
+        if self
+            .bands
+            .windows(2)
+            .any(|pair| pair[0].from >= pair[1].from)
+        {
+            return Err("the bands have to be sorted by their first level".to_string());
+        }
+        for band in self.bands.iter() {
+            band.validate()
+                .map_err(|e| format!("the band from level {}: {}", band.from, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn band(&self, level: u32) -> &DifficultyBand {
+        self.bands
+            .iter()
+            .rev()
+            .find(|band| band.from <= level)
+            .unwrap_or(&self.bands[0])
+    }
+}
+
+#[derive(Default)]
+pub struct DifficultyLoader;
+
+impl AssetLoader for DifficultyLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let table = ron::de::from_bytes::<DifficultyTable>(bytes)?;
+            table.validate().map_err(bevy::asset::Error::msg)?;
+            load_context.set_default_asset(LoadedAsset::new(table));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["difficulty.ron"]
+    }
+}
+
+/// Levels are generated from the loaded table from now on.
+pub fn apply_difficulty(
+    my_assets: Res<GameAssets>,
+    tables: Res<Assets<DifficultyTable>>,
+    mut info: ResMut<Info>,
+) {
+    if let Some(table) = tables.get(&my_assets.difficulty) {
+        info.difficulty = table.clone();
+        info.levels.clear();
+    }
+}
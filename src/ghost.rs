@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    console::Cheats, replay, tick::GameClock, tuning::Tuning, Block, GameAssets, GameState, Info,
+    MainCharacter, Progress,
+};
+
+/// Ticks between two samples of a run, the ghost glides between them.
+const SAMPLE_TICKS: u32 = 6;
+
+/// The best run at a level, the fastest to reach the threshold.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Ghost {
+    ticks: u32,
+    /// Fingerprint of the rules the run was played with, ghosts from other rules are
+    /// hidden and replaced.
+    #[serde(default)]
+    rules: u64,
+    /// Where the character was and how far along the level was, every few ticks.
+    samples: Vec<(Vec2, f32)>,
+}
+
+impl Ghost {
+    /// The ghost after this many ticks, it stays put at the end of its run.
+    fn at(&self, tick: f32) -> (Vec2, f32) {
+        let at = (tick / SAMPLE_TICKS as f32).max(0.0);
+        let i = at as usize;
+        match (self.samples.get(i), self.samples.get(i + 1)) {
+            (Some(a), Some(b)) => {
+                let t = at.fract();
+                (a.0.lerp(b.0, t), a.1 + (b.1 - a.1) * t)
+            }
+            _ => self
+                .samples
+                .last()
+                .map_or((Vec2::ZERO, 1.0), |last| (last.0, 1.0)),
+        }
+    }
+}
+
+/// The ghosts of every level, saved with the profile.
+#[derive(Resource, Default, Clone, Serialize, Deserialize)]
+pub struct Ghosts(HashMap<u32, Ghost>);
+
+impl Ghosts {
+    fn get(&self, level: u32, rules: u64) -> Option<&Ghost> {
+        self.0.get(&level).filter(|ghost| ghost.rules == rules)
+    }
+}
+
+/// The run being played, it becomes the ghost if it beats the current one.
+#[derive(Resource, Default)]
+pub struct GhostRun {
+    samples: Vec<(Vec2, f32)>,
+    pub done: bool,
+    rules: u64,
+}
+
+#[derive(Component)]
+pub struct GhostSprite;
+
+fn level_progress(blocks: &Query<(&Block, &Transform)>, info: &mut Info, prog: &Progress) -> f32 {
+    let sum: f32 = blocks
+        .iter()
+        .map(|(block, tr)| block.rel(tr.translation))
+        .sum();
+    sum / info.get_level(prog).point_threshold
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn start_ghost(
+    mut commands: Commands,
+    my_assets: Res<GameAssets>,
+    ghosts: Res<Ghosts>,
+    prog: Res<Progress>,
+    cheats: Res<Cheats>,
+    mut run: ResMut<GhostRun>,
+    tuning: Res<Tuning>,
+    info: Res<Info>,
+) {
+    let band = info.difficulty.band(prog.current_level);
+    *run = GhostRun {
+        done: cheats.active(),
+        rules: replay::fingerprint(&(&*tuning, band)),
+        ..default()
+    };
+    if let Some(ghost) = ghosts.get(prog.current_level, run.rules) {
+        let (pos, _) = ghost.at(0.0);
+        commands
+            .spawn(SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    index: 0,
+                    color: Color::rgba(1.0, 1.0, 1.0, 0.35),
+                    ..default()
+                },
+                texture_atlas: my_assets.lolle.clone(),
+                transform: Transform::from_translation(pos.extend(15.0)),
+                ..default()
+            })
+            .insert(GhostSprite);
+    }
+}
+
+/// The run can't become the ghost of the rules it started with once the tuning changes.
+pub fn drop_stale_ghost(
+    mut run: ResMut<GhostRun>,
+    tuning: Res<Tuning>,
+    info: Res<Info>,
+    prog: Res<Progress>,
+) {
+    if run.done || !tuning.is_changed() {
+        return;
+    }
+    let band = info.difficulty.band(prog.current_level);
+    if replay::fingerprint(&(&*tuning, band)) != run.rules {
+        run.done = true;
+    }
+}
+
+pub fn finish_ghost(mut run: ResMut<GhostRun>) {
+    run.done = true;
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn record_ghost(
+    mut run: ResMut<GhostRun>,
+    mut ghosts: ResMut<Ghosts>,
+    main_char: Query<&Transform, With<MainCharacter>>,
+    blocks: Query<(&Block, &Transform)>,
+    mut info: ResMut<Info>,
+    prog: Res<Progress>,
+    clock: Res<GameClock>,
+    state: Res<State<GameState>>,
+) {
+    // the ticks of a replay aren't a run, it would replace the ghost with itself
+    if run.done || *state.current() == GameState::Replay {
+        return;
+    }
+    if prog.level_complete {
+        run.done = true;
+        // the level was already counted as completed, the ghost belongs to the one before
+        let level = prog.current_level - 1;
+        let ticks = clock.tick;
+        if ghosts.get(level, run.rules).is_none_or(|g| ticks < g.ticks) {
+            let samples = std::mem::take(&mut run.samples);
+            let rules = run.rules;
+            ghosts.0.insert(
+                level,
+                Ghost {
+                    ticks,
+                    rules,
+                    samples,
+                },
+            );
+        }
+        return;
+    }
+    // the clock counts the current tick already
+    if (clock.tick - 1).is_multiple_of(SAMPLE_TICKS) {
+        if let Ok(tr) = main_char.get_single() {
+            let progress = level_progress(&blocks, &mut info, &prog);
+            run.samples.push((tr.translation.truncate(), progress));
+        }
+    }
+}
+
+pub fn move_ghost(
+    mut ghost_sprite: Query<&mut Transform, With<GhostSprite>>,
+    ghosts: Res<Ghosts>,
+    run: Res<GhostRun>,
+    prog: Res<Progress>,
+    clock: Res<GameClock>,
+) {
+    if let (Ok(mut tr), Some(ghost)) = (
+        ghost_sprite.get_single_mut(),
+        ghosts.get(prog.current_level, run.rules),
+    ) {
+        let (pos, _) = ghost.at(clock.tick as f32 + clock.alpha());
+        tr.translation = pos.extend(tr.translation.z);
+    }
+}
+
+/// How the run compares to the ghost at the same moment.
+pub fn ghost_indicator(
+    mut egui_context: ResMut<EguiContext>,
+    ghosts: Res<Ghosts>,
+    run: Res<GhostRun>,
+    blocks: Query<(&Block, &Transform)>,
+    mut info: ResMut<Info>,
+    prog: Res<Progress>,
+    clock: Res<GameClock>,
+) {
+    let ghost = match ghosts.get(prog.current_level, run.rules) {
+        Some(ghost) if !prog.level_complete => ghost,
+        _ => return,
+    };
+    let (_, ghost_progress) = ghost.at(clock.tick as f32);
+    let diff = ((level_progress(&blocks, &mut info, &prog) - ghost_progress) * 100.0) as i32;
+    let (text, color) = if diff >= 0 {
+        (
+            format!("{}% ahead of your best", diff),
+            egui::Color32::GREEN,
+        )
+    } else {
+        (format!("{}% behind your best", -diff), egui::Color32::RED)
+    };
+    egui::Window::new("GhostIndicator")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::RIGHT_TOP, egui::Vec2::splat(0.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.colored_label(color, text);
+        });
+}
@@ -0,0 +1,280 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{settings::Settings, GameState};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Dash,
+    Magnet,
+    Dynamite,
+    Pause,
+    Restart,
+}
+
+impl Action {
+    pub const ALL: [Action; 9] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Dash,
+        Action::Magnet,
+        Action::Dynamite,
+        Action::Pause,
+        Action::Restart,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveUp => "Move up",
+            Action::MoveDown => "Move down",
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::Dash => "Dash",
+            Action::Magnet => "Magnet",
+            Action::Dynamite => "Dynamite",
+            Action::Pause => "Pause",
+            Action::Restart => "Restart",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Pad(GamepadButtonType),
+}
+
+impl Binding {
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(MouseButton::Left) => "Left click".to_string(),
+            Binding::Mouse(MouseButton::Right) => "Right click".to_string(),
+            Binding::Mouse(MouseButton::Middle) => "Middle click".to_string(),
+            Binding::Mouse(MouseButton::Other(n)) => format!("Mouse {}", n),
+            Binding::Pad(button) => format!("Pad {:?}", button),
+        }
+    }
+
+    /// Keyboard and mouse are played together, the pad on its own.
+    fn on_pad(&self) -> bool {
+        matches!(self, Binding::Pad(_))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Bindings {
+    map: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Binding::*;
+        use GamepadButtonType::*;
+        Self {
+            map: HashMap::from([
+                (
+                    Action::MoveUp,
+                    vec![Key(KeyCode::W), Key(KeyCode::Up), Pad(DPadUp)],
+                ),
+                (
+                    Action::MoveDown,
+                    vec![Key(KeyCode::S), Key(KeyCode::Down), Pad(DPadDown)],
+                ),
+                (
+                    Action::MoveLeft,
+                    vec![Key(KeyCode::A), Key(KeyCode::Left), Pad(DPadLeft)],
+                ),
+                (
+                    Action::MoveRight,
+                    vec![Key(KeyCode::D), Key(KeyCode::Right), Pad(DPadRight)],
+                ),
+                (
+                    Action::Dash,
+                    vec![Key(KeyCode::Space), Pad(South), Pad(RightTrigger)],
+                ),
+                (
+                    Action::Magnet,
+                    vec![Key(KeyCode::J), Pad(West), Pad(LeftTrigger2)],
+                ),
+                (
+                    Action::Dynamite,
+                    vec![Key(KeyCode::K), Pad(East), Pad(RightTrigger2)],
+                ),
+                (Action::Pause, vec![Key(KeyCode::Escape), Pad(Start)]),
+                (Action::Restart, vec![Key(KeyCode::R), Pad(Select)]),
+            ]),
+        }
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &[Binding] {
+        self.map.get(&action).map_or(&[], |b| b.as_slice())
+    }
+
+    /// Binds the action to this input instead of the others of its device, taking it away
+    /// from any other action. The bindings of the other device stay.
+    pub fn rebind(&mut self, action: Action, binding: Binding) {
+        for bindings in self.map.values_mut() {
+            bindings.retain(|b| *b != binding);
+        }
+        let bindings = self.map.entry(action).or_default();
+        bindings.retain(|b| b.on_pad() != binding.on_pad());
+        bindings.push(binding);
+    }
+
+    /// What to show the player for an action, like `[Space]`.
+    pub fn prompt(&self, action: Action) -> String {
+        let labels: Vec<String> = self.get(action).iter().map(|b| b.label()).collect();
+        if labels.is_empty() {
+            "[unbound]".to_string()
+        } else {
+            format!("[{}]", labels.join("/"))
+        }
+    }
+}
+
+/// The actions the player is performing this frame, whatever device they come from.
+#[derive(Resource, Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    /// Up to unit length, shorter when a stick is only partly pushed.
+    pub movement: Vec2,
+    /// The right stick or a dragged touch button, for the aimed abilities.
+    pub aim: Vec2,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    /// Whether the action was just pressed, the press is handled and won't be seen again
+    /// this frame, by a state entered right after for example.
+    pub fn consume(&mut self, action: Action) -> bool {
+        self.just_pressed.remove(&action)
+    }
+
+    pub fn press(&mut self, action: Action) {
+        self.pressed.insert(action);
+    }
+}
+
+/// The pad the player is using, the first one connected.
+#[derive(Resource, Default)]
+pub struct ActiveGamepad(pub Option<Gamepad>);
+
+pub fn gamepad_connections(
+    mut gamepad_events: EventReader<GamepadEvent>,
+    gamepads: Res<Gamepads>,
+    mut active: ResMut<ActiveGamepad>,
+    mut state: ResMut<State<GameState>>,
+) {
+    for event in gamepad_events.iter() {
+        match event.event_type {
+            GamepadEventType::Connected(_) if active.0.is_none() => {
+                info!("using gamepad {:?}", event.gamepad);
+                active.0 = Some(event.gamepad);
+            }
+            GamepadEventType::Disconnected if active.0 == Some(event.gamepad) => {
+                active.0 = gamepads.iter().find(|g| *g != event.gamepad);
+                info!("gamepad disconnected, now using {:?}", active.0);
+                if *state.current() == GameState::Gameplay {
+                    state.push(GameState::Paused).ok();
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub fn update_actions(
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    active: Res<ActiveGamepad>,
+    settings: Res<Settings>,
+    mut actions: ResMut<ActionState>,
+) {
+    actions.pressed.clear();
+    actions.just_pressed.clear();
+    for action in Action::ALL {
+        for binding in settings.bindings.get(action) {
+            let (pressed, just_pressed) = match binding {
+                Binding::Key(key) => (
+                    keyboard_input.pressed(*key),
+                    keyboard_input.just_pressed(*key),
+                ),
+                Binding::Mouse(button) => (
+                    mouse_input.pressed(*button),
+                    mouse_input.just_pressed(*button),
+                ),
+                Binding::Pad(button) => active.0.map_or((false, false), |pad| {
+                    let button = GamepadButton::new(pad, *button);
+                    (
+                        gamepad_buttons.pressed(button),
+                        gamepad_buttons.just_pressed(button),
+                    )
+                }),
+            };
+            if pressed {
+                actions.pressed.insert(action);
+            }
+            if just_pressed {
+                actions.just_pressed.insert(action);
+            }
+        }
+    }
+    let axis = |pos: Action, neg: Action| {
+        actions.pressed(pos) as i32 as f32 - actions.pressed(neg) as i32 as f32
+    };
+    let mut movement = Vec2::new(
+        axis(Action::MoveRight, Action::MoveLeft),
+        axis(Action::MoveUp, Action::MoveDown),
+    );
+    if let Some(pad) = active.0 {
+        let stick = |axis| gamepad_axes.get(GamepadAxis::new(pad, axis)).unwrap_or(0.0);
+        movement += Vec2::new(
+            stick(GamepadAxisType::LeftStickX),
+            stick(GamepadAxisType::LeftStickY),
+        );
+        actions.aim = Vec2::new(
+            stick(GamepadAxisType::RightStickX),
+            stick(GamepadAxisType::RightStickY),
+        );
+    } else {
+        actions.aim = Vec2::ZERO;
+    }
+    actions.movement = movement.clamp_length_max(1.0);
+}
+
+/// The first key, pad or non-primary mouse button pressed this frame, for rebinding.
+pub fn pressed_binding(
+    keyboard_input: &Input<KeyCode>,
+    mouse_input: &Input<MouseButton>,
+    gamepad_buttons: &Input<GamepadButton>,
+) -> Option<Binding> {
+    if let Some(key) = keyboard_input.get_just_pressed().next() {
+        return Some(Binding::Key(*key));
+    }
+    if let Some(button) = gamepad_buttons.get_just_pressed().next() {
+        return Some(Binding::Pad(button.button_type));
+    }
+    // the left button is how the menu is clicked, so it can't be captured here
+    mouse_input
+        .get_just_pressed()
+        .find(|b| **b != MouseButton::Left)
+        .map(|b| Binding::Mouse(*b))
+}
@@ -1,12 +1,8 @@
-use rand::{rngs::ThreadRng, Rng};
-use std::time::Duration;
-
-use bevy::{
-    audio::AudioSink,
-    diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
-    prelude::*,
-    sprite::MaterialMesh2dBundle,
-};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::{collections::HashMap, time::Duration};
+
+use bevy::{input::InputSystem, prelude::*, time::Stopwatch};
 use bevy_asset_loader::prelude::*;
 use bevy_egui::{egui, EguiContext, EguiPlugin, EguiSettings};
 use bevy_rapier2d::prelude::*;
@@ -14,35 +10,98 @@ use bevy_vfx_bag::{
     image::chromatic_aberration::{ChromaticAberration, ChromaticAberrationPlugin},
     BevyVfxBagPlugin, PostProcessingInput,
 };
+use serde::{Deserialize, Serialize};
+
+mod aim;
+mod audio;
+mod bot;
+mod camera;
+mod console;
+mod debug;
+mod dialogue;
+mod difficulty;
+mod ghost;
+mod input;
+mod menu;
+mod particles;
+mod pause;
+mod profiles;
+mod replay;
+mod save;
+mod settings;
+mod shop;
+mod sim;
+mod tick;
+mod title;
+mod touch;
+mod tuning;
+use aim::AimGuide;
+use audio::GameAudioPlugin;
+use camera::{CameraShakePlugin, CAMERA_SCALE};
+use console::Cheats;
+use debug::DevToolsPlugin;
+use dialogue::{Dialogue, DialogueLoader, DialogueScript};
+use difficulty::{DifficultyBand, DifficultyLoader, DifficultyTable};
+use ghost::GhostSprite;
+use input::ActiveGamepad;
+use menu::MenuNav;
+use particles::ParticlesPlugin;
+use profiles::Profiles;
+use replay::{ReplayViewer, TickInput};
+use save::SaveGame;
+use settings::{Settings, SettingsMenu};
+use shop::{Upgrade, Upgrades};
+use sim::SimulationPlugin;
+use tick::{GameClock, Interpolated, Smoothed, TICK};
+use title::TitleMenu;
+use touch::TouchControls;
+use tuning::{Tuning, TuningLoader};
 
 fn main() {
+    if let Some(levels) = bot::levels_arg() {
+        bot::report(levels);
+        return;
+    }
     App::new()
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            window: WindowDescriptor {
-                width: 700.0,
-                height: 700.0,
-                fit_canvas_to_parent: true,
-                ..default()
-            },
-            ..default()
-        }))
+        .add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    window: WindowDescriptor {
+                        width: 700.0,
+                        height: 700.0,
+                        fit_canvas_to_parent: true,
+                        ..default()
+                    },
+                    ..default()
+                })
+                // the tuning reloads when its file is saved, there are no files on the web
+                .set(AssetPlugin {
+                    watch_for_changes: cfg!(not(target_arch = "wasm32")),
+                    ..default()
+                }),
+        )
+        .add_asset::<DialogueScript>()
+        .init_asset_loader::<DialogueLoader>()
+        .add_asset::<DifficultyTable>()
+        .init_asset_loader::<DifficultyLoader>()
+        .add_asset::<Tuning>()
+        .init_asset_loader::<TuningLoader>()
         .add_loading_state(
             LoadingState::new(GameState::AssetLoading)
-                .continue_to_state(GameState::LevelLoading)
+                .continue_to_state(GameState::Title)
                 .with_collection::<GameAssets>(),
         )
         .add_state(GameState::AssetLoading)
         .insert_resource(Msaa { samples: 1 })
-        .init_resource::<Info>()
         .init_resource::<MousePos>()
-        .init_resource::<Soundtrack>()
-        .insert_resource(Progress {
-            current_level: 1,
-            golden_apples: 0,
-            level_complete: false,
-            end_timer: Timer::from_seconds(3.0, TimerMode::Once),
-            level_timer: Timer::from_seconds(20.0, TimerMode::Once),
-        })
+        .init_resource::<SettingsMenu>()
+        .init_resource::<Profiles>()
+        .init_resource::<TitleMenu>()
+        .init_resource::<Dialogue>()
+        .init_resource::<ActiveGamepad>()
+        .init_resource::<MenuNav>()
+        .init_resource::<TouchControls>()
+        .add_event::<SaveGame>()
         .insert_resource(ClearColor(Color::BLACK))
         .add_plugin(BevyVfxBagPlugin) // This needs to be added for any effect to work
         .add_plugin(ChromaticAberrationPlugin)
@@ -52,33 +111,113 @@ fn main() {
             magnitude_b: 0.0,
             ..default()
         })
-        .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
-        //.add_plugin(RapierDebugRenderPlugin::default())
+        .add_plugin(SimulationPlugin)
+        .add_plugin(GameAudioPlugin)
+        .add_plugin(ParticlesPlugin)
+        .add_plugin(CameraShakePlugin)
+        .add_plugin(DevToolsPlugin)
         .add_startup_system(setup_graphics)
         .add_startup_system(setup_egui)
-        .add_startup_system(setup_physics)
-        //.add_system_set(SystemSet::on_exit(GameState::AssetLoading).with_system(setup_audio))
-        .add_system_set(SystemSet::on_update(GameState::LevelLoading).with_system(setup_level))
+        .add_system_set(
+            SystemSet::on_exit(GameState::AssetLoading).with_system(difficulty::apply_difficulty),
+        )
+        .add_system_set(
+            SystemSet::on_enter(GameState::Title).with_system(profiles::refresh_profiles),
+        )
+        .add_system_set(SystemSet::on_update(GameState::Title).with_system(title::title_screen))
+        .add_system_set(SystemSet::on_update(GameState::Title).with_system(profiles::profile_menu))
+        .add_system_set(SystemSet::on_update(GameState::Title).with_system(replay::replay_menu))
+        .add_system_set(SystemSet::on_enter(GameState::Intro).with_system(dialogue::start_intro))
+        .add_system_set(SystemSet::on_enter(GameState::Intro).with_system(save::request_save))
+        .add_system_set(SystemSet::on_update(GameState::Intro).with_system(dialogue::dialogue_box))
+        .add_system_set(
+            SystemSet::on_enter(GameState::Gameplay).with_system(dialogue::level_dialogue),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Gameplay)
+                .with_system(dialogue::start_dialogue.after(dialogue::queue_dialogue)),
+        )
+        .add_system_set(SystemSet::on_enter(GameState::Dialogue).with_system(pause::freeze_physics))
+        .add_system_set(
+            SystemSet::on_update(GameState::Dialogue).with_system(dialogue::dialogue_box),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Dialogue).with_system(pause::unfreeze_physics),
+        )
+        .add_system_set(
+            SystemSet::on_enter(GameState::Gameplay).with_system(replay::start_recording),
+        )
+        .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(ghost::move_ghost))
+        .add_system_set(
+            SystemSet::on_update(GameState::Gameplay).with_system(ghost::ghost_indicator),
+        )
         .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(animate_sprite))
         .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(block_color))
-        .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(movement))
-        .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(check_finish))
+        .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(aim::update_aim))
+        .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(aim::aim_guides))
         .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(show_level_progress))
-        .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(mass_increase))
-        .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(attract))
-        .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(explode))
-        .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(audio_volumes))
-        .add_system_set(SystemSet::on_exit(GameState::Gameplay).with_system(teardown_level))
+        .add_system_set(
+            SystemSet::on_update(GameState::Gameplay).with_system(touch::draw_touch_controls),
+        )
+        .add_system_set(SystemSet::on_update(GameState::Gameplay).with_system(pause::pause_input))
+        .add_system_set(SystemSet::on_exit(GameState::Gameplay).with_system(replay::save_recording))
+        .add_system_set(SystemSet::on_update(GameState::Replay).with_system(animate_sprite))
+        .add_system_set(SystemSet::on_update(GameState::Replay).with_system(block_color))
+        .add_system_set(SystemSet::on_update(GameState::Replay).with_system(aim::aim_guides))
+        .add_system_set(SystemSet::on_update(GameState::Replay).with_system(show_level_progress))
+        .add_system_set(
+            SystemSet::on_update(GameState::Replay).with_system(replay::replay_controls),
+        )
+        .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(pause::freeze_physics))
+        .add_system_set(SystemSet::on_update(GameState::Paused).with_system(pause::pause_menu))
+        .add_system_set(SystemSet::on_exit(GameState::Paused).with_system(pause::unfreeze_physics))
+        .add_system_set(SystemSet::on_enter(GameState::Failed).with_system(pause::freeze_physics))
+        .add_system_set(SystemSet::on_update(GameState::Failed).with_system(pause::fail_menu))
+        .add_system_set(SystemSet::on_exit(GameState::Failed).with_system(pause::unfreeze_physics))
+        .add_system_set(SystemSet::on_enter(GameState::Shop).with_system(save::request_save))
+        .add_system_set(SystemSet::on_update(GameState::Shop).with_system(shop::shop))
+        .add_system_set(SystemSet::on_exit(GameState::Shop).with_system(save::request_save))
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            input::gamepad_connections.after(InputSystem),
+        )
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            input::update_actions
+                .after(input::gamepad_connections)
+                .after(InputSystem),
+        )
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            touch::touch_controls.after(input::update_actions),
+        )
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            menu::update_menu_nav
+                .after(input::gamepad_connections)
+                .after(InputSystem),
+        )
         .add_system(mouse_pos)
         .add_system(reset_chroma)
+        .add_system(settings::settings_menu)
+        .add_system(save::save_game)
+        .add_system(dialogue::queue_dialogue)
+        .add_system(dialogue::type_dialogue)
+        .add_system(tuning::apply_tuning)
         .add_plugin(EguiPlugin)
         .run();
 }
 
-fn reset_chroma(mut chroma: ResMut<ChromaticAberration>, time: Res<Time>) {
-    chroma.magnitude_r *= 0.8;
-    chroma.magnitude_g *= 0.8;
-    chroma.magnitude_b *= 0.8;
+fn reset_chroma(mut chroma: ResMut<ChromaticAberration>, time: Res<Time>, settings: Res<Settings>) {
+    // the same fade whatever the frame rate, 0.8 per frame at 60 fps
+    let decay = if settings.screen_effects {
+        0.8_f32.powf(time.delta_seconds() * 60.0)
+    } else {
+        0.0
+    };
+    chroma.magnitude_r *= decay;
+    chroma.magnitude_g *= decay;
+    chroma.magnitude_b *= decay;
 }
 
 #[derive(AssetCollection, Resource)]
@@ -92,18 +231,33 @@ struct GameAssets {
     lo: Handle<AudioSource>,
     #[asset(path = "hit.ogg")]
     hit: Handle<AudioSource>,
+    #[asset(path = "story.dialogue.ron")]
+    dialogue: Handle<DialogueScript>,
+    #[asset(path = "levels.difficulty.ron")]
+    difficulty: Handle<DifficultyTable>,
+    // only held so the tuning stays loaded, apply_tuning picks it up from its events
+    #[allow(dead_code)]
+    #[asset(path = "gameplay.tuning.ron")]
+    tuning: Handle<Tuning>,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
 enum GameState {
     AssetLoading,
+    Title,
+    Intro,
     LevelLoading,
     Gameplay,
+    Paused,
+    Failed,
+    Replay,
+    Dialogue,
+    Shop,
 }
 
 fn setup_graphics(mut commands: Commands) {
     let mut camera = Camera2dBundle::default();
-    camera.projection.scale = 0.666;
+    camera.projection.scale = CAMERA_SCALE;
     commands.spawn(camera).insert(PostProcessingInput);
 }
 
@@ -122,19 +276,26 @@ fn place_block(commands: &mut Commands, block: Block) {
             block.base_pos - Vec3::new(0.0, 0.0, 5.0),
         )));
 
+    let transform = Transform::from_translation(block.base_pos);
     commands
-        .spawn(SpriteBundle {
-            sprite: Sprite {
-                color: block.color,
-                custom_size: Some(block.base_size),
-                ..default()
-            },
+        .spawn(SpatialBundle {
+            transform,
             ..default()
         })
+        .with_children(|parent| {
+            parent
+                .spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: block.color,
+                        custom_size: Some(block.base_size),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .insert(Smoothed);
+        })
         .insert(block.clone())
-        .insert(TransformBundle::from(Transform::from_translation(
-            block.base_pos,
-        )))
+        .insert(Interpolated::new(transform))
         .insert(Collider::cuboid(
             block.base_size.x * 0.5,
             block.base_size.y * 0.5,
@@ -153,38 +314,12 @@ fn place_block(commands: &mut Commands, block: Block) {
         .insert(RigidBody::Dynamic);
 }
 
-#[derive(Resource, Default)]
-struct Soundtrack {
-    hi: Handle<AudioSink>,
-    lo: Handle<AudioSink>,
-}
-
-fn setup_audio(
-    my_assets: Res<GameAssets>,
-    audio_sinks: Res<Assets<AudioSink>>,
-    audio: Res<Audio>,
-    mut sinks: ResMut<Soundtrack>,
-) {
-    sinks.hi = audio_sinks.get_handle(audio.play_with_settings(
-        my_assets.hi.clone(),
-        PlaybackSettings {
-            repeat: true,
-            volume: 0.1,
-            ..default()
-        },
-    ));
-    sinks.lo = audio_sinks.get_handle(audio.play_with_settings(
-        my_assets.lo.clone(),
-        PlaybackSettings {
-            repeat: true,
-            volume: 0.1,
-            ..default()
-        },
-    ));
-}
-
 fn setup_physics(mut commands: Commands, mut rapier_conf: ResMut<RapierConfiguration>) {
     rapier_conf.gravity = Vec2::new(0.0, 0.0);
+    rapier_conf.timestep_mode = TimestepMode::Fixed {
+        dt: TICK,
+        substeps: 1,
+    };
     commands
         .spawn(Collider::cuboid(700.0, 50.0))
         .insert(TransformBundle::from(Transform::from_xyz(0.0, 340.0, 0.0)));
@@ -220,32 +355,46 @@ fn setup_level(
     my_assets: Res<GameAssets>,
     mut info: ResMut<Info>,
     prog: Res<Progress>,
+    upgrades: Res<Upgrades>,
+    tuning: Res<Tuning>,
     mut bg_color: ResMut<ClearColor>,
-    mut state: ResMut<State<GameState>>,
 ) {
     let level = info.get_level(&prog);
     for block in level.blocks.iter() {
         place_block(&mut commands, block.clone());
     }
 
-    bg_color.0 = level.back_color.clone();
+    bg_color.0 = level.back_color;
 
+    let transform = Transform::from_translation(level.spawnpoint);
     commands
-        .spawn(SpriteSheetBundle {
-            sprite: TextureAtlasSprite::new(0),
-            texture_atlas: my_assets.lolle.clone(),
-            ..Default::default()
+        .spawn(SpatialBundle {
+            transform,
+            ..default()
         })
-        .insert(AnimationTimer(Timer::from_seconds(
-            0.25,
-            TimerMode::Repeating,
-        )))
+        .with_children(|parent| {
+            parent
+                .spawn(SpriteSheetBundle {
+                    sprite: TextureAtlasSprite::new(0),
+                    texture_atlas: my_assets.lolle.clone(),
+                    ..Default::default()
+                })
+                .insert(AnimationTimer(Timer::from_seconds(
+                    0.25,
+                    TimerMode::Repeating,
+                )))
+                .insert(Smoothed);
+        })
+        .insert(Interpolated::new(transform))
         .insert(RigidBody::Dynamic)
-        .insert(Collider::ball(30.0))
-        .insert(Restitution::coefficient(0.7))
+        .insert(Collider::ball(tuning.character.radius))
+        .insert(ColliderMassProperties::Density(
+            upgrades.character_density(),
+        ))
+        .insert(Restitution::coefficient(tuning.character.restitution))
         .insert(Damping {
-            linear_damping: 5.0,
-            angular_damping: 1.0,
+            linear_damping: tuning.character.linear_damping,
+            angular_damping: tuning.character.angular_damping,
         })
         .insert(LockedAxes::ROTATION_LOCKED)
         .insert(Velocity {
@@ -255,15 +404,20 @@ fn setup_level(
         .insert(ActiveEvents::COLLISION_EVENTS)
         .insert(ActiveEvents::CONTACT_FORCE_EVENTS)
         .insert(MainCharacter {
-            dash_timer: Timer::from_seconds(1.0, TimerMode::Once),
-            attract_timer: Timer::from_seconds(0.5, TimerMode::Once),
-            explode_timer: Timer::from_seconds(0.8, TimerMode::Once),
-        })
-        .insert(TransformBundle::from(Transform::from_translation(
-            level.spawnpoint,
-        )));
+            dash_timer: Timer::from_seconds(upgrades.dash_cooldown(&tuning), TimerMode::Once),
+            attract_timer: Timer::from_seconds(tuning.character.attract_cooldown, TimerMode::Once),
+            explode_timer: Timer::from_seconds(tuning.character.explode_cooldown, TimerMode::Once),
+            dash_dir: Vec2::ZERO,
+        });
+    aim::spawn_aim_guides(&mut commands);
+}
 
-    state.set(GameState::Gameplay).ok();
+fn start_level(viewer: Res<ReplayViewer>, mut state: ResMut<State<GameState>>) {
+    if viewer.watching() {
+        state.set(GameState::Replay).ok();
+    } else {
+        state.set(GameState::Gameplay).ok();
+    }
 }
 
 fn teardown_level(
@@ -271,22 +425,29 @@ fn teardown_level(
     query_blocks: Query<(Entity, &Block)>,
     query_main: Query<(Entity, &MainCharacter)>,
     query_bases: Query<(Entity, &BlockBase)>,
+    query_guides: Query<(Entity, &AimGuide)>,
+    query_ghosts: Query<(Entity, &GhostSprite)>,
 ) {
     for (ent, _) in query_blocks.iter() {
-        commands.entity(ent).despawn();
+        commands.entity(ent).despawn_recursive();
     }
     for (ent, _) in query_main.iter() {
-        commands.entity(ent).despawn();
+        commands.entity(ent).despawn_recursive();
     }
     for (ent, _) in query_bases.iter() {
         commands.entity(ent).despawn();
     }
+    for (ent, _) in query_guides.iter() {
+        commands.entity(ent).despawn();
+    }
+    for (ent, _) in query_ghosts.iter() {
+        commands.entity(ent).despawn();
+    }
 }
 
 fn mass_increase(
     mut main_char: Query<(&mut MainCharacter, &mut AdditionalMassProperties)>,
     block_query: Query<(&Block, &Transform)>,
-    prog: Res<Progress>,
 ) {
     if let Ok((_, mut mass)) = main_char.get_single_mut() {
         let sum: f32 = block_query
@@ -302,21 +463,35 @@ fn check_finish(
     block_query: Query<(&Block, &Transform)>,
     mut info: ResMut<Info>,
     mut prog: ResMut<Progress>,
-    time: Res<Time>,
+    mut stats: ResMut<Stats>,
+    mut clock: ResMut<GameClock>,
+    cheats: Res<Cheats>,
 ) {
     if block_query.iter().count() == 0 {
         return;
     }
+    stats.play_time += TICK;
     if !prog.level_complete {
         let sum: f32 = block_query
             .iter()
             .map(|(block, tr)| block.rel(tr.translation))
             .sum();
         let level = info.get_level(&prog);
-        prog.level_timer.tick(time.delta());
+        if !cheats.god {
+            prog.level_timer.tick(tick::tick_duration());
+        }
+        prog.level_stopwatch.tick(tick::tick_duration());
         if sum >= level.point_threshold {
-            if !prog.level_timer.finished() {
+            // a cheated run finishes the level, but it doesn't count
+            if !prog.level_timer.finished() && !cheats.active() {
                 prog.golden_apples += 1;
+                stats.apples_earned += 1;
+            }
+            stats.levels_completed += 1;
+            if !cheats.active() {
+                let time = prog.level_stopwatch.elapsed_secs();
+                let best = prog.best_times.entry(level.id).or_insert(time);
+                *best = best.min(time);
             }
             prog.current_level += 1;
             prog.level_complete = true;
@@ -334,13 +509,17 @@ fn check_finish(
             */
         }
     } else {
-        prog.end_timer.tick(time.delta());
-        if prog.end_timer.finished() {
+        prog.end_timer.tick(tick::tick_duration());
+        // a replay stays on the finished level
+        if prog.end_timer.finished() && *state.current() == GameState::Gameplay {
             let level = info.get_level(&prog);
             prog.level_timer.set_duration(level.duration);
             prog.level_timer.reset();
+            prog.level_stopwatch.reset();
             prog.level_complete = false;
-            state.set(GameState::LevelLoading).ok();
+            // the ticks left in the frame would start scoring the next level
+            clock.halted = true;
+            state.set(GameState::Shop).ok();
         }
     }
 }
@@ -350,6 +529,7 @@ fn show_level_progress(
     mut info: ResMut<Info>,
     prog: Res<Progress>,
     mut egui_context: ResMut<EguiContext>,
+    cheats: Res<Cheats>,
 ) {
     let sum: f32 = block_query
         .iter()
@@ -380,7 +560,9 @@ fn show_level_progress(
                         ));
                 ui.add(progress_bar);
             } else {
-                if !prog.level_timer.finished() {
+                if cheats.active() {
+                    ui.label("Cheated, the level doesn't count");
+                } else if !prog.level_timer.finished() {
                     ui.label(format!(
                         "You won a golden apple! You now have {}",
                         prog.golden_apples
@@ -390,23 +572,6 @@ fn show_level_progress(
                 }
             }
         });
-    if !prog.end_timer.finished() && prog.level_complete {
-        if [5, 10, 15].contains(&prog.golden_apples) {
-            egui::Window::new("UpgProgress")
-                .title_bar(false)
-                .resizable(false)
-                .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::splat(0.0))
-                .show(egui_context.ctx_mut(), |ui| {
-                    let text = match prog.golden_apples {
-                        5 => "You have unlocked Dash! [Space]",
-                        10 => "You have unlocked Magnet! [J]",
-                        15 => "You have unlocked Dynamite! [K]",
-                        _ => "Level passed",
-                    };
-                    ui.label(text);
-                });
-        }
-    }
     egui::Window::new("TimeProgress")
         .title_bar(false)
         .resizable(false)
@@ -424,29 +589,16 @@ fn show_level_progress(
         });
 }
 
-fn block_color(mut block_query: Query<(&Block, &Transform, &mut Sprite)>) {
-    for (block, tr, mut sprite) in block_query.iter_mut() {
+fn block_color(
+    block_query: Query<(&Block, &Transform, &Children)>,
+    mut sprites: Query<&mut Sprite>,
+) {
+    for (block, tr, children) in block_query.iter() {
         let amt = block.rel(tr.translation);
         let lerp = block.color * (1.0 - amt) + block.color_target * amt;
-        sprite.color = lerp;
-    }
-}
-
-fn hit_fx(
-    my_assets: Res<GameAssets>,
-    audio: Res<Audio>,
-    mut impact_events: EventReader<ContactForceEvent>,
-) {
-    for ev in impact_events.iter() {
-        if ev.max_force_magnitude > 1.0 {
-            audio.play_with_settings(
-                my_assets.hit.clone(),
-                PlaybackSettings {
-                    repeat: false,
-                    volume: 0.3,
-                    ..default()
-                },
-            );
+        let mut iter = sprites.iter_many_mut(children.iter());
+        while let Some(mut sprite) = iter.fetch_next() {
+            sprite.color = lerp;
         }
     }
 }
@@ -454,6 +606,7 @@ fn hit_fx(
 #[derive(Clone)]
 struct Level {
     id: u32,
+    seed: u64,
     blocks: Vec<Block>,
     point_threshold: f32,
     spawnpoint: Vec3,
@@ -474,18 +627,18 @@ struct Layout {
 }
 
 impl Layout {
-    fn gen_quarter(mag: u32, rng: &mut ThreadRng, walls_prob: f32) -> Layout {
+    fn gen_quarter(mag: u32, rng: &mut ChaCha8Rng, band: &DifficultyBand) -> Layout {
         let mut layout = Layout { rects: vec![] };
         for _ in 0..mag {
             // generate random box size
-            let size = if rng.gen_bool(1.0 - walls_prob as f64) {
+            let size = if rng.gen_bool(1.0 - band.wall_probability as f64) {
                 Vec2::new(
-                    rng.gen_range(2..10) as f32 * 5.0,
-                    rng.gen_range(2..10) as f32 * 5.0,
+                    Layout::side(rng, band.block_size),
+                    Layout::side(rng, band.block_size),
                 )
             } else {
-                let long = rng.gen_range(15..25) as f32 * 5.0;
-                let short = rng.gen_range(3..10) as f32 * 5.0;
+                let long = Layout::side(rng, band.wall_length);
+                let short = Layout::side(rng, band.wall_width);
                 if rng.gen_bool(0.5) {
                     Vec2::new(long, short)
                 } else {
@@ -540,8 +693,13 @@ impl Layout {
         layout.clip_oob(&Vec2::new(350.0, 290.0))
     }
 
-    fn gen(mag: u32, rng: &mut ThreadRng, walls_prob: f32) -> Layout {
-        let mut layout = Layout::gen_quarter(mag, rng, walls_prob);
+    /// A side between `min` and `max`, on a 5 pixel grid.
+    fn side(rng: &mut ChaCha8Rng, (min, max): (u32, u32)) -> f32 {
+        rng.gen_range(min / 5..=max / 5) as f32 * 5.0
+    }
+
+    fn gen(mag: u32, rng: &mut ChaCha8Rng, band: &DifficultyBand) -> Layout {
+        let mut layout = Layout::gen_quarter(mag, rng, band);
         let stamp = layout.clone();
         layout = layout.merge(&stamp.mirror_x());
         layout = layout.merge(&stamp.mirror_y());
@@ -552,7 +710,7 @@ impl Layout {
     fn drag(&mut self, rect_i: usize, dir: &Vec2, distance: f32) {
         let mut dragging = vec![rect_i];
         for _ in 0..distance as i32 {
-            let movevec = dir.clone();
+            let movevec = *dir;
             for _ in 0..100 {
                 let mut pushed = vec![];
                 for drag in dragging.iter() {
@@ -675,43 +833,44 @@ impl Layout {
 
     fn cast(&self, start: &Vec2, dir: &Vec2, rect: &Rect) -> Rect {
         let mut min = Vec2::new(0.0, 0.0);
-        for i in 0..100 {
+        for _ in 0..100 {
             for r in self.rects.iter() {
-                let sample = r.pos * dir.clone();
-                if (sample * dir.clone()).length() > min.length() {
+                let sample = r.pos * *dir;
+                if (sample * *dir).length() > min.length() {
                     min = sample;
                     break;
                 }
-                let sample = (r.pos + r.size) * dir.clone();
-                if (sample * dir.clone()).length() > min.length() {
+                let sample = (r.pos + r.size) * *dir;
+                if (sample * *dir).length() > min.length() {
                     min = sample;
                     break;
                 }
             }
             let casted = Rect {
-                pos: min + start.clone(),
-                size: rect.size.clone(),
+                pos: min + *start,
+                size: rect.size,
             };
             if !self.rects.iter().any(|r| Layout::intersects(r, &casted)) {
                 return casted;
             }
         }
-        let mut pos = start.clone() + (dir.clone() * (self.rects.len() as f32 * 50.0));
-        return Rect {
+        let pos = *start + (*dir * (self.rects.len() as f32 * 50.0));
+        Rect {
             pos,
-            size: rect.size.clone(),
-        };
+            size: rect.size,
+        }
     }
 }
 
 impl Level {
-    fn gen(num: u32) -> Level {
-        let mut rng = rand::thread_rng();
-        let hue: f32 = rng.gen_range(0.0..360.0);
+    fn gen(num: u32, seed: u64, band: &DifficultyBand) -> Level {
+        // a fixed algorithm, saved seeds and replays rebuild the same level after an update
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let hue: f32 = rng.gen_range(band.theme.hue.0..=band.theme.hue.1);
         let color = Color::hsl(hue, 1.0, 0.5);
         let target_color = Color::hsl((hue + 137.0).clamp(0.0, 360.0), 1.0, 0.5);
         let wall_color = Color::hsl((hue + 137.0 * 2.0).clamp(0.0, 360.0), 0.5, 0.4);
-        let back_color = Color::hsl(0.0, 0.0, 0.1);
+        let back_color = Color::hsl(0.0, 0.0, band.theme.background);
         let movable = Block {
             color,
             base_color: color * 0.7,
@@ -725,19 +884,21 @@ impl Level {
             density: 0.0,
             ..default()
         };
-        let (mag, walls_prob) = match num {
-            1..=2 => (5, 0.0),
-            3..=6 => (10, 0.03),
-            7..=15 => (20, 0.06),
-            n => ((n * 2).max(40), 0.1),
+        let heavy = Block {
+            color: color * 0.6,
+            base_color: color * 0.4,
+            density: band.hazards.heavy_density,
+            ..movable.clone()
         };
-        let layout = Layout::gen(mag, &mut rng, walls_prob);
+        let layout = Layout::gen(band.magnitude(num), &mut rng, band);
         let blocks: Vec<Block> = layout
             .rects
             .iter()
             .map(|r| {
-                if r.size.length() >= 15.0 * 5.0 {
+                if r.size.max_element() >= band.wall_length.0 as f32 {
                     wall.mov(r.pos + r.size * 0.5).siz(r.size)
+                } else if band.hazards.heavy > 0.0 && rng.gen_bool(band.hazards.heavy as f64) {
+                    heavy.mov(r.pos + r.size * 0.5).siz(r.size)
                 } else {
                     movable.mov(r.pos + r.size * 0.5).siz(r.size)
                 }
@@ -747,25 +908,24 @@ impl Level {
         let wallnum = blocks.iter().filter(|b| b.density == 0.0).count();
         Level {
             id: num,
+            seed,
             back_color,
             blocks: blocks.clone(),
-            point_threshold: blocknum as f32 * 0.76,
+            point_threshold: blocknum as f32 * band.threshold,
             spawnpoint: Vec3::new(0.0, 0.0, 20.0),
-            duration: Duration::from_secs((blocknum + wallnum * 5) as u64),
+            duration: Duration::from_secs_f32(
+                blocknum as f32 * band.time_per_block + wallnum as f32 * band.time_per_wall,
+            ),
             color,
         }
     }
 }
 
-#[derive(Resource)]
-struct Info {
+#[derive(Resource, Default)]
+pub struct Info {
     levels: Vec<Level>,
-}
-
-impl Default for Info {
-    fn default() -> Self {
-        Self { levels: vec![] }
-    }
+    seeds: HashMap<u32, u64>,
+    difficulty: DifficultyTable,
 }
 
 impl Info {
@@ -773,7 +933,12 @@ impl Info {
         if let Some(level) = self.levels.iter().find(|l| l.id == prog.current_level) {
             level.clone()
         } else {
-            let level = Level::gen(prog.current_level);
+            let seed = *self
+                .seeds
+                .entry(prog.current_level)
+                .or_insert_with(|| rand::thread_rng().gen());
+            let band = self.difficulty.band(prog.current_level);
+            let level = Level::gen(prog.current_level, seed, band);
             self.levels.push(level.clone());
             level
         }
@@ -781,12 +946,37 @@ impl Info {
 }
 
 #[derive(Resource)]
-struct Progress {
+pub struct Progress {
     current_level: u32,
     golden_apples: i32,
     level_complete: bool,
     end_timer: Timer,
     level_timer: Timer,
+    level_stopwatch: Stopwatch,
+    best_times: HashMap<u32, f32>,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            current_level: 1,
+            golden_apples: 0,
+            level_complete: false,
+            end_timer: Timer::from_seconds(3.0, TimerMode::Once),
+            level_timer: Timer::from_seconds(20.0, TimerMode::Once),
+            level_stopwatch: Stopwatch::new(),
+            best_times: HashMap::new(),
+        }
+    }
+}
+
+/// Lifetime numbers of a profile, they never reset.
+#[derive(Resource, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+    levels_completed: u32,
+    apples_earned: u32,
+    play_time: f32,
 }
 
 #[derive(Component)]
@@ -800,7 +990,6 @@ struct Block {
     color: Color,
     color_target: Color,
     max_distance: f32,
-    points_mul: f32,
     linear_damping: f32,
     angular_damping: f32,
     restitution: f32,
@@ -819,7 +1008,6 @@ impl Default for Block {
             angular_damping: 1.0,
             restitution: 0.3,
             density: 1.0,
-            points_mul: 1.0,
             color_target: Color::RED,
         }
     }
@@ -829,8 +1017,7 @@ impl Block {
     fn rel(&self, pos: Vec3) -> f32 {
         let distance = pos.distance(self.base_pos);
         let amt = distance / self.max_distance;
-        let amt = amt.max(0.0).min(1.0);
-        amt
+        amt.clamp(0.0, 1.0)
     }
 
     fn mov(&self, pos: Vec2) -> Block {
@@ -851,6 +1038,25 @@ struct MainCharacter {
     dash_timer: Timer,
     attract_timer: Timer,
     explode_timer: Timer,
+    /// Where an aimed dash goes, zero for a dash that follows the movement.
+    dash_dir: Vec2,
+}
+
+/// Sent from the tick when an ability fires, for the effects.
+enum AbilityUsed {
+    Dash {
+        pos: Vec2,
+        dir: Vec2,
+    },
+    /// Every tick the magnet is held, with the blocks it pulls.
+    Magnet {
+        pos: Vec2,
+        pulled: Vec<Vec2>,
+    },
+    Dynamite {
+        pos: Vec2,
+        radius: f32,
+    },
 }
 
 #[derive(Component)]
@@ -880,67 +1086,52 @@ fn animate_sprite(
     }
 }
 
-fn audio_volumes(
-    main_query: Query<(&Velocity, &MainCharacter)>,
-    sinks: Res<Soundtrack>,
-    audio_sinks: Res<Assets<AudioSink>>,
-) {
-    if let Ok((vel, _)) = main_query.get_single() {
-        let speed = (vel.linvel.length() * 0.001).min(1.0);
-        let amt = speed;
-
-        if let Some(sink) = audio_sinks.get(&sinks.hi) {
-            sink.set_volume(amt * 0.2);
-        }
-        if let Some(sink) = audio_sinks.get(&sinks.lo) {
-            sink.set_volume((1.0 - amt) * 0.2);
-        }
-    }
-}
-
-const EXPLODE_COST: i32 = 15;
-const EXPLODE_POWER: f32 = 100000.0;
-
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn explode(
     mut main_char: Query<(&mut MainCharacter, &Transform), (Without<Block>, With<MainCharacter>)>,
     mut block_query: Query<
         (&Block, &Transform, &mut Velocity, &ColliderMassProperties),
         (Without<MainCharacter>, With<Block>),
     >,
-    keyboard_input: Res<Input<KeyCode>>,
-    time: Res<Time>,
-    prog: Res<Progress>,
+    input: Res<TickInput>,
+    upgrades: Res<Upgrades>,
+    tuning: Res<Tuning>,
+    cheats: Res<Cheats>,
     mut chroma: ResMut<ChromaticAberration>,
+    mut abilities: EventWriter<AbilityUsed>,
 ) {
     if let Ok((mut main, mtr)) = main_char.get_single_mut() {
-        main.explode_timer.tick(time.delta());
-        if main.explode_timer.finished()
-            && keyboard_input.pressed(KeyCode::J)
-            && prog.golden_apples >= EXPLODE_COST
-        {
+        main.explode_timer.tick(tick::tick_duration());
+        let ready = main.explode_timer.finished() || cheats.infinite;
+        let unlocked = upgrades.has(Upgrade::Dynamite) || cheats.infinite;
+        if ready && input.dynamite && unlocked {
             main.explode_timer.reset();
             let mut affected = Vec2::new(0.0, 0.0);
             for (_, btr, mut v, coll) in block_query.iter_mut() {
-                match coll {
-                    ColliderMassProperties::Density(d) => {
-                        if d == &0.0 {
-                            continue;
-                        }
+                if let ColliderMassProperties::Density(d) = coll {
+                    if d == &0.0 {
+                        continue;
                     }
-                    _ => (),
                 }
                 let delta = mtr.translation - btr.translation;
                 let delta = Vec2::new(delta.x, delta.y);
                 let mag = delta.length();
-                if mag > 20.0 && mag < 100.0 {
-                    let pow = -delta
-                        * (1.0 / (mag * mag))
-                        * (time.delta_seconds() * 60.0)
-                        * EXPLODE_POWER;
+                let caught = if input.aimed_dynamite {
+                    aim::in_cone(-delta, input.aim, upgrades.explode_radius(&tuning) * 1.5)
+                } else {
+                    mag > 20.0 && mag < upgrades.explode_radius(&tuning)
+                };
+                if caught {
+                    // a single kick, it doesn't depend on the tick length
+                    let pow = -delta * (1.0 / (mag * mag)) * tuning.explode_power;
                     v.linvel += pow;
                     affected += pow;
                 }
             }
+            abilities.send(AbilityUsed::Dynamite {
+                pos: mtr.translation.truncate(),
+                radius: upgrades.explode_radius(&tuning),
+            });
             let amt = (affected / 500.0).length().min(0.5);
             chroma.magnitude_r += 0.02 * amt;
             chroma.magnitude_g += 0.02 * amt;
@@ -952,44 +1143,51 @@ fn explode(
     }
 }
 
-const ATTRACT_COST: i32 = 10;
-const ATTRACT_POWER: f32 = 1000.0;
-
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn attract(
     mut main_char: Query<(&mut MainCharacter, &Transform), (Without<Block>, With<MainCharacter>)>,
     mut block_query: Query<
         (&Block, &Transform, &mut Velocity, &ColliderMassProperties),
         (Without<MainCharacter>, With<Block>),
     >,
-    keyboard_input: Res<Input<KeyCode>>,
-    time: Res<Time>,
-    prog: Res<Progress>,
+    input: Res<TickInput>,
+    upgrades: Res<Upgrades>,
+    tuning: Res<Tuning>,
+    cheats: Res<Cheats>,
     mut chroma: ResMut<ChromaticAberration>,
+    mut abilities: EventWriter<AbilityUsed>,
 ) {
     if let Ok((mut main, mtr)) = main_char.get_single_mut() {
-        main.attract_timer.tick(time.delta());
-        if keyboard_input.pressed(KeyCode::K) && prog.golden_apples >= ATTRACT_COST {
+        main.attract_timer.tick(tick::tick_duration());
+        if input.magnet && (upgrades.has(Upgrade::Magnet) || cheats.infinite) {
             main.attract_timer.reset();
             let mut affected = Vec2::new(0.0, 0.0);
+            let mut pulled = Vec::new();
             for (_, btr, mut v, coll) in block_query.iter_mut() {
-                match coll {
-                    ColliderMassProperties::Density(d) => {
-                        if d == &0.0 {
-                            continue;
-                        }
+                if let ColliderMassProperties::Density(d) = coll {
+                    if d == &0.0 {
+                        continue;
                     }
-                    _ => (),
                 }
                 let delta = mtr.translation - btr.translation;
                 let delta = Vec2::new(delta.x, delta.y);
                 let mag = delta.length();
-                if mag > 20.0 && mag < 100.0 {
-                    let pow =
-                        delta * (1.0 / (mag * mag)) * (time.delta_seconds() * 60.0) * ATTRACT_POWER;
+                let caught = if input.aimed_magnet {
+                    aim::in_beam(-delta, input.aim, upgrades.attract_radius(&tuning) * 2.0)
+                } else {
+                    mag > 20.0 && mag < upgrades.attract_radius(&tuning)
+                };
+                if caught {
+                    let pow = delta * (1.0 / (mag * mag)) * TICK * upgrades.attract_power(&tuning);
                     v.linvel += pow;
                     affected += pow;
+                    pulled.push(btr.translation.truncate());
                 }
             }
+            abilities.send(AbilityUsed::Magnet {
+                pos: mtr.translation.truncate(),
+                pulled,
+            });
             let amt = (affected / 1000.0).length().min(0.1);
             chroma.magnitude_r += 0.02 * amt;
             chroma.magnitude_g += 0.02 * amt;
@@ -1001,49 +1199,46 @@ fn attract(
     }
 }
 
-const ACCELERATION: f32 = 50.0;
-const DASH_COST: i32 = 5;
-const DASH_BOOST: f32 = 2.5;
-
 fn movement(
-    mut main_char: Query<(&mut MainCharacter, &Transform, &mut Velocity)>,
-    mut touch_input: EventReader<TouchInput>,
-    keyboard_input: Res<Input<KeyCode>>,
-    mouse_input: Res<Input<MouseButton>>,
-    mouse_pos: Res<MousePos>,
-    time: Res<Time>,
-    prog: Res<Progress>,
+    mut main_char: Query<(&mut MainCharacter, &mut Velocity, &Transform)>,
+    input: Res<TickInput>,
+    upgrades: Res<Upgrades>,
+    tuning: Res<Tuning>,
+    cheats: Res<Cheats>,
     mut chroma: ResMut<ChromaticAberration>,
+    mut abilities: EventWriter<AbilityUsed>,
 ) {
-    if let Ok((mut main, tr, mut vel)) = main_char.get_single_mut() {
-        let pos = Vec2::new(tr.translation.x, tr.translation.y);
-        let mut acc = ACCELERATION;
-        main.dash_timer.tick(time.delta());
-        if main.dash_timer.finished() {
-            if keyboard_input.pressed(KeyCode::Space) && prog.golden_apples >= DASH_COST {
-                main.dash_timer.reset();
-                acc *= DASH_BOOST * 2.0;
-                chroma.magnitude_r += 0.002;
-                chroma.magnitude_g += 0.0002;
-                chroma.magnitude_b += 0.0002;
-            }
+    if let Ok((mut main, mut vel, mtr)) = main_char.get_single_mut() {
+        let mut acc = upgrades.acceleration(&tuning);
+        main.dash_timer.tick(tick::tick_duration());
+        let ready = main.dash_timer.finished() || cheats.infinite;
+        let unlocked = upgrades.has(Upgrade::Dash) || cheats.infinite;
+        if ready && input.dash && unlocked {
+            main.dash_timer.reset();
+            main.dash_dir = if input.aimed_dash {
+                input.aim
+            } else {
+                Vec2::ZERO
+            };
+            acc *= upgrades.dash_boost(&tuning) * 2.0;
+            chroma.magnitude_r += 0.002;
+            chroma.magnitude_g += 0.0002;
+            chroma.magnitude_b += 0.0002;
         } else if main.dash_timer.percent() < 0.25 {
-            acc *= DASH_BOOST;
+            acc *= upgrades.dash_boost(&tuning);
         }
-        let mut vec_acc = Vec2::new(
-            keyboard_input.pressed(KeyCode::D) as i32 as f32
-                - keyboard_input.pressed(KeyCode::A) as i32 as f32,
-            keyboard_input.pressed(KeyCode::W) as i32 as f32
-                - keyboard_input.pressed(KeyCode::S) as i32 as f32,
-        );
-        if mouse_input.pressed(MouseButton::Left) {
-            vec_acc += (mouse_pos.world - pos).normalize_or_zero();
+        let mut vec_acc = input.movement;
+        if main.dash_timer.percent() < 0.25 && main.dash_dir != Vec2::ZERO {
+            vec_acc = main.dash_dir;
         }
-        for touch in touch_input.iter() {
-            vec_acc += (touch.position - pos).normalize_or_zero();
+        if ready && input.dash && unlocked {
+            abilities.send(AbilityUsed::Dash {
+                pos: mtr.translation.truncate(),
+                dir: vec_acc.normalize_or_zero(),
+            });
         }
 
-        vel.linvel += vec_acc.normalize_or_zero() * acc * time.delta_seconds() * 60.0;
+        vel.linvel += vec_acc.clamp_length_max(1.0) * acc * TICK;
 
         if main.dash_timer.percent() < 0.25 {
             chroma.magnitude_r += 0.002;
@@ -1070,19 +1265,10 @@ fn mouse_pos(
     mut cursor_moved_events: EventReader<CursorMoved>,
     windows: Res<Windows>,
     mut mousepos: ResMut<MousePos>,
-    query_camera: Query<(&Camera, &GlobalTransform)>,
 ) {
-    if let Some((camera, camera_transform)) = query_camera.get_single().ok() {
-        if let Some(window) = windows.get_primary() {
-            for event in cursor_moved_events.iter() {
-                let window_size = Vec2::new(window.width() as f32, window.height() as f32);
-                let ndc = (event.position / window_size) * 2.0 - Vec2::ONE;
-                let ndc_to_world =
-                    camera_transform.compute_matrix() * camera.projection_matrix().inverse();
-                let world_pos = ndc_to_world.project_point3(ndc.extend(-1.0));
-                let world_pos: Vec2 = world_pos.truncate();
-                mousepos.world = world_pos;
-            }
+    if let Some(window) = windows.get_primary() {
+        for event in cursor_moved_events.iter() {
+            mousepos.world = camera::window_to_world(window, event.position);
         }
     }
 }
@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use crate::input::ActiveGamepad;
+
+/// Lets the pad walk through the buttons of whatever menu is on screen.
+///
+/// Menus draw their buttons through [`MenuNav::button`], which counts them, so the
+/// d-pad can move the focus and the south button can press the focused one.
+#[derive(Resource, Default)]
+pub struct MenuNav {
+    focus: Option<usize>,
+    count: usize,
+    confirm: bool,
+}
+
+impl MenuNav {
+    pub fn button(
+        &mut self,
+        ui: &mut egui::Ui,
+        enabled: bool,
+        text: impl Into<egui::WidgetText>,
+    ) -> bool {
+        let focused = self.focus == Some(self.count);
+        self.count += 1;
+        let mut button = egui::Button::new(text);
+        if focused {
+            button = button.stroke(egui::Stroke::new(2.0, egui::Color32::WHITE));
+        }
+        let response = ui.add_enabled(enabled, button);
+        response.clicked() || (enabled && focused && self.confirm)
+    }
+
+    /// Drops this frame's press, something else took the input.
+    pub fn cancel(&mut self) {
+        self.confirm = false;
+    }
+}
+
+pub fn update_menu_nav(
+    mut nav: ResMut<MenuNav>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    active: Res<ActiveGamepad>,
+) {
+    let pressed = |button| {
+        active
+            .0
+            .is_some_and(|pad| gamepad_buttons.just_pressed(GamepadButton::new(pad, button)))
+    };
+    // buttons drawn last frame, the menu is not expected to change from one frame to the next
+    let count = nav.count;
+    nav.count = 0;
+    nav.confirm = pressed(GamepadButtonType::South);
+    nav.focus = if count == 0 {
+        None
+    } else if pressed(GamepadButtonType::DPadDown) {
+        Some(nav.focus.map_or(0, |f| (f + 1) % count))
+    } else if pressed(GamepadButtonType::DPadUp) {
+        Some(nav.focus.map_or(count - 1, |f| (f + count - 1) % count))
+    } else {
+        nav.focus.map(|f| f.min(count - 1))
+    };
+}
@@ -0,0 +1,272 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+
+use crate::{AbilityUsed, Block};
+
+/// Sprites kept around for particles, new ones are skipped while they're all in use.
+const POOL_SIZE: usize = 512;
+const PARTICLE_Z: f32 = 10.0;
+/// Contacts below this force raise nothing and stay silent, a hit this strong raises the
+/// most and plays at full volume.
+const QUIET_FORCE: f32 = 1.0;
+const BIG_FORCE: f32 = 10000.0;
+/// Hits per frame that get particles, the rest are lost in the crowd.
+const HITS_PER_FRAME: usize = 8;
+const DUST_PER_HIT: f32 = 6.0;
+const DEBRIS_PER_HIT: f32 = 4.0;
+const DASH_SPARKS: usize = 12;
+const RING_PARTICLES: usize = 48;
+/// Chance per tick that a pulled block gives off a streak.
+const STREAK_CHANCE: f64 = 0.1;
+const STREAK_SPEED: f32 = 300.0;
+
+/// Dust, debris, sparks and streaks, drawn with a fixed pool of sprites.
+pub struct ParticlesPlugin;
+
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ParticlePool>()
+            .add_startup_system(fill_pool)
+            .add_system(impact_particles)
+            .add_system(ability_particles)
+            .add_system(
+                update_particles
+                    .after(impact_particles)
+                    .after(ability_particles),
+            );
+    }
+}
+
+#[derive(Component, Clone, Default)]
+struct Particle {
+    velocity: Vec2,
+    /// Part of the velocity lost per second.
+    drag: f32,
+    age: f32,
+    lifetime: f32,
+    /// Shrinks and fades to nothing over the lifetime.
+    size: Vec2,
+    color: Color,
+    /// Shards and streaks point along their velocity.
+    oriented: bool,
+}
+
+#[derive(Resource, Default)]
+struct ParticlePool {
+    free: Vec<Entity>,
+    queued: Vec<(Vec2, Particle)>,
+}
+
+impl ParticlePool {
+    fn emit(&mut self, pos: Vec2, particle: Particle) {
+        if self.queued.len() < self.free.len() {
+            self.queued.push((pos, particle));
+        }
+    }
+}
+
+fn fill_pool(mut commands: Commands, mut pool: ResMut<ParticlePool>) {
+    pool.free = (0..POOL_SIZE)
+        .map(|_| {
+            commands
+                .spawn(SpriteBundle {
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                })
+                .insert(Particle::default())
+                .id()
+        })
+        .collect();
+}
+
+/// From 0 to 1, forces go over orders of magnitude and so do the eye and the ear.
+pub fn strength(force: f32) -> f32 {
+    ((force / QUIET_FORCE).ln() / (BIG_FORCE / QUIET_FORCE).ln()).clamp(0.0, 1.0)
+}
+
+fn random_dir(rng: &mut impl Rng) -> Vec2 {
+    Vec2::from_angle(rng.gen_range(0.0..TAU))
+}
+
+/// Dust where things collide, and shards of the block when it hits a wall.
+fn impact_particles(
+    mut impact_events: EventReader<ContactForceEvent>,
+    bodies: Query<(&Transform, Option<&Block>)>,
+    mut pool: ResMut<ParticlePool>,
+) {
+    let mut rng = rand::thread_rng();
+    for ev in impact_events
+        .iter()
+        .filter(|ev| ev.max_force_magnitude > QUIET_FORCE)
+        .take(HITS_PER_FRAME)
+    {
+        let hit: Vec<_> = bodies.iter_many([ev.collider1, ev.collider2]).collect();
+        if hit.is_empty() {
+            continue;
+        }
+        let strength = strength(ev.max_force_magnitude);
+        let pos = hit
+            .iter()
+            .map(|(tr, _)| tr.translation.truncate())
+            .sum::<Vec2>()
+            / hit.len() as f32;
+        for _ in 0..(strength * DUST_PER_HIT).round() as usize {
+            pool.emit(
+                pos,
+                Particle {
+                    velocity: random_dir(&mut rng) * rng.gen_range(20.0..60.0) * (0.5 + strength),
+                    drag: 3.0,
+                    lifetime: rng.gen_range(0.3..0.6),
+                    size: Vec2::splat(rng.gen_range(4.0..8.0)),
+                    color: Color::rgba(0.8, 0.8, 0.8, 0.5),
+                    ..default()
+                },
+            );
+        }
+
+        let wall = hit
+            .iter()
+            .any(|(_, block)| block.is_some_and(|b| b.density == 0.0));
+        let block = hit
+            .iter()
+            .find(|(_, block)| block.is_some_and(|b| b.density > 0.0));
+        if let (true, Some((tr, Some(block)))) = (wall, block) {
+            for _ in 0..(strength * DEBRIS_PER_HIT).ceil() as usize {
+                pool.emit(
+                    tr.translation.truncate(),
+                    Particle {
+                        velocity: random_dir(&mut rng) * rng.gen_range(80.0..160.0),
+                        drag: 2.0,
+                        lifetime: rng.gen_range(0.4..0.8),
+                        size: Vec2::new(6.0, 2.0),
+                        color: block.color,
+                        oriented: true,
+                        ..default()
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Sparks behind a dash, a ring for dynamite and streaks into the magnet.
+fn ability_particles(mut abilities: EventReader<AbilityUsed>, mut pool: ResMut<ParticlePool>) {
+    let mut rng = rand::thread_rng();
+    for ability in abilities.iter() {
+        match ability {
+            AbilityUsed::Dash { pos, dir } => {
+                for _ in 0..DASH_SPARKS {
+                    let spread = if *dir == Vec2::ZERO {
+                        random_dir(&mut rng)
+                    } else {
+                        Vec2::from_angle(rng.gen_range(-0.6..0.6)).rotate(-*dir)
+                    };
+                    pool.emit(
+                        *pos,
+                        Particle {
+                            velocity: spread * rng.gen_range(150.0..250.0),
+                            drag: 4.0,
+                            lifetime: rng.gen_range(0.2..0.4),
+                            size: Vec2::new(5.0, 1.5),
+                            color: Color::YELLOW,
+                            oriented: true,
+                            ..default()
+                        },
+                    );
+                }
+            }
+            AbilityUsed::Dynamite { pos, radius } => {
+                for i in 0..RING_PARTICLES {
+                    let dir = Vec2::from_angle(i as f32 / RING_PARTICLES as f32 * TAU);
+                    pool.emit(
+                        *pos,
+                        Particle {
+                            // the drag stops the ring around the blast radius
+                            velocity: dir * *radius * 3.0,
+                            drag: 3.0,
+                            lifetime: 0.35,
+                            size: Vec2::new(8.0, 3.0),
+                            color: Color::ORANGE,
+                            oriented: true,
+                            ..default()
+                        },
+                    );
+                }
+            }
+            AbilityUsed::Magnet { pos, pulled } => {
+                for from in pulled.iter() {
+                    if !rng.gen_bool(STREAK_CHANCE) {
+                        continue;
+                    }
+                    let delta = *pos - *from;
+                    pool.emit(
+                        *from,
+                        Particle {
+                            velocity: delta.normalize_or_zero() * STREAK_SPEED,
+                            lifetime: (delta.length() / STREAK_SPEED).min(0.5),
+                            size: Vec2::new(8.0, 1.5),
+                            color: Color::rgba(0.6, 0.8, 1.0, 0.8),
+                            oriented: true,
+                            ..default()
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Takes sprites from the pool for the new particles, moves the others and returns the
+/// finished ones.
+fn update_particles(
+    mut pool: ResMut<ParticlePool>,
+    mut particles: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        &mut Sprite,
+        &mut Visibility,
+    )>,
+    time: Res<Time>,
+) {
+    let pool = &mut *pool;
+    for (pos, new) in pool.queued.drain(..) {
+        let entity = match pool.free.pop() {
+            Some(entity) => entity,
+            None => break,
+        };
+        if let Ok((_, mut particle, mut tr, _, mut visibility)) = particles.get_mut(entity) {
+            *particle = new;
+            tr.translation = pos.extend(PARTICLE_Z);
+            tr.rotation = Quat::IDENTITY;
+            visibility.is_visible = true;
+        }
+    }
+
+    let dt = time.delta_seconds();
+    for (entity, mut particle, mut tr, mut sprite, mut visibility) in particles.iter_mut() {
+        if !visibility.is_visible {
+            continue;
+        }
+        particle.age += dt;
+        if particle.age >= particle.lifetime {
+            visibility.is_visible = false;
+            pool.free.push(entity);
+            continue;
+        }
+        let drag = (1.0 - particle.drag * dt).max(0.0);
+        particle.velocity *= drag;
+        tr.translation += (particle.velocity * dt).extend(0.0);
+        if particle.oriented && particle.velocity != Vec2::ZERO {
+            tr.rotation = Quat::from_rotation_z(particle.velocity.y.atan2(particle.velocity.x));
+        }
+        let left = 1.0 - particle.age / particle.lifetime;
+        sprite.custom_size = Some(particle.size * left);
+        let mut color = particle.color;
+        color.set_a(color.a() * left);
+        sprite.color = color;
+    }
+}
@@ -0,0 +1,140 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    dialogue::DialogueTrigger,
+    input::{Action, ActionState},
+    menu::MenuNav,
+    save::SaveGame,
+    settings::{Settings, SettingsMenu},
+    tick::GameClock,
+    GameState, Info, Progress,
+};
+
+pub fn pause_input(
+    mut actions: ResMut<ActionState>,
+    mut prog: ResMut<Progress>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if actions.consume(Action::Pause) {
+        state.push(GameState::Paused).ok();
+    } else if actions.consume(Action::Restart) && !prog.level_complete {
+        restart_level(&mut prog, &mut state);
+    }
+}
+
+pub fn freeze_physics(mut rapier_conf: ResMut<RapierConfiguration>) {
+    rapier_conf.physics_pipeline_active = false;
+}
+
+pub fn unfreeze_physics(mut rapier_conf: ResMut<RapierConfiguration>) {
+    rapier_conf.physics_pipeline_active = true;
+}
+
+/// Starts the current level over, from any state stacked on top of the gameplay.
+pub fn restart_level(prog: &mut Progress, state: &mut State<GameState>) {
+    prog.level_timer.reset();
+    prog.level_stopwatch.reset();
+    prog.level_complete = false;
+    state.replace(GameState::LevelLoading).ok();
+}
+
+/// Leaves the level for the title screen, Continue starts it over with a full timer.
+pub fn quit_to_title(prog: &mut Progress, info: &mut Info, state: &mut State<GameState>) {
+    let level = info.get_level(prog);
+    prog.level_timer.set_duration(level.duration);
+    prog.level_timer.reset();
+    prog.level_stopwatch.reset();
+    prog.level_complete = false;
+    state.replace(GameState::Title).ok();
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn pause_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut nav: ResMut<MenuNav>,
+    mut settings_menu: ResMut<SettingsMenu>,
+    mut actions: ResMut<ActionState>,
+    mut prog: ResMut<Progress>,
+    mut info: ResMut<Info>,
+    mut state: ResMut<State<GameState>>,
+    mut save: EventWriter<SaveGame>,
+) {
+    if settings_menu.open {
+        return;
+    }
+    if actions.consume(Action::Pause) {
+        state.pop().ok();
+        return;
+    }
+    egui::Window::new("Paused")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::splat(0.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label("Paused");
+            if nav.button(ui, true, "Resume") {
+                state.pop().ok();
+            }
+            if nav.button(ui, true, "Restart level") {
+                restart_level(&mut prog, &mut state);
+            }
+            if nav.button(ui, true, "Settings") {
+                settings_menu.open = true;
+            }
+            if nav.button(ui, true, "Quit to title") {
+                save.send(SaveGame);
+                quit_to_title(&mut prog, &mut info, &mut state);
+            }
+        });
+}
+
+/// Costs the golden apple when the level timer runs out, or the level in hard fail mode.
+pub fn timer_fail(
+    prog: Res<Progress>,
+    settings: Res<Settings>,
+    mut clock: ResMut<GameClock>,
+    mut state: ResMut<State<GameState>>,
+    mut dialogue: EventWriter<DialogueTrigger>,
+) {
+    let watching = *state.current() == GameState::Replay;
+    if !prog.level_timer.just_finished() || prog.level_complete || watching {
+        return;
+    }
+    if settings.hard_fail {
+        // the level can't be finished anymore in the ticks left of this frame
+        clock.halted = true;
+        state.push(GameState::Failed).ok();
+    } else {
+        dialogue.send(DialogueTrigger::TimerFail);
+    }
+}
+
+/// The retry screen, when the timer ran out in hard fail mode.
+pub fn fail_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut nav: ResMut<MenuNav>,
+    mut actions: ResMut<ActionState>,
+    mut prog: ResMut<Progress>,
+    mut info: ResMut<Info>,
+    mut state: ResMut<State<GameState>>,
+    mut save: EventWriter<SaveGame>,
+) {
+    let mut retry = actions.consume(Action::Restart);
+    egui::Window::new("Failed")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::splat(0.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label("Out of time");
+            retry |= nav.button(ui, true, "Retry");
+            if nav.button(ui, true, "Quit to title") {
+                save.send(SaveGame);
+                quit_to_title(&mut prog, &mut info, &mut state);
+            }
+        });
+    if retry {
+        restart_level(&mut prog, &mut state);
+    }
+}
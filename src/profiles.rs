@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::{
+    menu::MenuNav,
+    save::{self, GameData, SaveData},
+};
+
+#[derive(Resource, Default)]
+pub struct Profiles {
+    pub active: Option<String>,
+    pub menu_open: bool,
+    names: Vec<String>,
+    saves: HashMap<String, Result<SaveData, String>>,
+    new_name: String,
+    renaming: Option<(String, String)>,
+    deleting: Option<String>,
+    error: Option<String>,
+}
+
+enum ProfileAction {
+    Select(String),
+    Create(String),
+    Rename(String, String),
+    Duplicate(String),
+    Delete(String),
+}
+
+impl Profiles {
+    fn refresh(&mut self) {
+        self.names = match save::read_index() {
+            Ok(names) => names,
+            Err(e) => {
+                self.error = Some(format!("Could not list the profiles, {}", e));
+                vec![]
+            }
+        };
+        // every profile is read on its own so a corrupt one only takes itself down
+        self.saves = self
+            .names
+            .iter()
+            .map(|name| {
+                (
+                    name.clone(),
+                    save::read_profile(name).map_err(|e| e.to_string()),
+                )
+            })
+            .collect();
+    }
+
+    fn check_name(&self, name: &str) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > 20 {
+            return Err("Names are 1 to 20 characters long".to_string());
+        }
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == ' ' || c == '-' || c == '_')
+        {
+            return Err("Names can only have letters, digits, spaces, - and _".to_string());
+        }
+        if self.names.iter().any(|n| n == name) {
+            return Err(format!("There already is a profile named {}", name));
+        }
+        Ok(name.to_string())
+    }
+
+    fn copy_name(&self, name: &str) -> String {
+        let mut copy = format!("{} copy", name);
+        let mut i = 2;
+        while self.names.contains(&copy) {
+            copy = format!("{} copy {}", name, i);
+            i += 1;
+        }
+        copy
+    }
+
+    fn add(&mut self, name: String, data: &SaveData) -> Result<(), save::SaveError> {
+        save::write_profile(&name, data)?;
+        self.names.push(name);
+        save::write_index(&self.names)
+    }
+
+    fn remove(&mut self, name: &str) -> Result<(), save::SaveError> {
+        self.names.retain(|n| n != name);
+        save::write_index(&self.names)?;
+        save::remove_profile(name)
+    }
+
+    /// The saved progress of the active profile.
+    pub fn active_save(&self) -> Option<&SaveData> {
+        let name = self.active.as_ref()?;
+        self.saves.get(name)?.as_ref().ok()
+    }
+
+    /// Makes sure there's a profile to play with, creating the first one if needed.
+    pub fn ensure_active(&mut self, data: &mut GameData) -> Result<(), String> {
+        if self.active.is_some() {
+            return Ok(());
+        }
+        let mut name = "Player".to_string();
+        let mut i = 2;
+        while self.names.contains(&name) {
+            name = format!("Player {}", i);
+            i += 1;
+        }
+        self.run(ProfileAction::Create(name.clone()), data)?;
+        self.refresh();
+        self.run(ProfileAction::Select(name), data)
+    }
+
+    fn run(&mut self, action: ProfileAction, data: &mut GameData) -> Result<(), String> {
+        match action {
+            ProfileAction::Select(name) => {
+                let save = self.saves[&name].clone()?;
+                data.apply(save);
+                self.active = Some(name);
+                self.menu_open = false;
+            }
+            ProfileAction::Create(name) => {
+                let name = self.check_name(&name)?;
+                self.add(name, &SaveData::default())
+                    .map_err(|e| e.to_string())?;
+                self.new_name.clear();
+            }
+            ProfileAction::Rename(from, to) => {
+                let to = self.check_name(&to)?;
+                let save = self.saves[&from].clone()?;
+                self.add(to.clone(), &save).map_err(|e| e.to_string())?;
+                self.remove(&from).map_err(|e| e.to_string())?;
+                save::move_replays(&from, Some(&to), save.current_level)
+                    .map_err(|e| e.to_string())?;
+                self.renaming = None;
+                if self.active.as_ref() == Some(&from) {
+                    self.active = Some(to);
+                }
+            }
+            ProfileAction::Duplicate(name) => {
+                let save = self.saves[&name].clone()?;
+                let copy = self.copy_name(&name);
+                self.add(copy.clone(), &save).map_err(|e| e.to_string())?;
+                save::copy_replays(&name, &copy, save.current_level).map_err(|e| e.to_string())?;
+            }
+            ProfileAction::Delete(name) => {
+                self.remove(&name).map_err(|e| e.to_string())?;
+                if let Some(Ok(save)) = self.saves.get(&name) {
+                    save::move_replays(&name, None, save.current_level)
+                        .map_err(|e| e.to_string())?;
+                }
+                self.deleting = None;
+                if self.active.as_ref() == Some(&name) {
+                    self.active = None;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lists the profiles and picks one for the title screen, the last played if there's one.
+pub fn refresh_profiles(mut profiles: ResMut<Profiles>, mut data: GameData) {
+    profiles.error = None;
+    profiles.menu_open = false;
+    profiles.refresh();
+    if profiles.active_save().is_none() {
+        profiles.active = None;
+        let first = profiles
+            .names
+            .iter()
+            .find(|name| profiles.saves[*name].is_ok())
+            .cloned();
+        if let Some(name) = first {
+            if let Err(e) = profiles.run(ProfileAction::Select(name), &mut data) {
+                profiles.error = Some(e);
+            }
+        }
+    }
+}
+
+pub fn profile_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut profiles: ResMut<Profiles>,
+    mut data: GameData,
+    mut nav: ResMut<MenuNav>,
+) {
+    if !profiles.menu_open {
+        return;
+    }
+    let mut action = None;
+    egui::Window::new("Profiles")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::splat(0.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            let profiles = &mut *profiles;
+            egui::Grid::new("ProfileList").show(ui, |ui| {
+                for name in profiles.names.iter() {
+                    if let Some((from, to)) = &mut profiles.renaming {
+                        if *from == *name {
+                            ui.text_edit_singleline(to);
+                            if nav.button(ui, true, "Rename") {
+                                action = Some(ProfileAction::Rename(from.clone(), to.clone()));
+                            }
+                            if nav.button(ui, true, "Cancel") {
+                                profiles.renaming = None;
+                            }
+                            ui.end_row();
+                            continue;
+                        }
+                    }
+                    ui.label(name);
+                    match &profiles.saves[name] {
+                        Ok(save) => {
+                            ui.label(format!(
+                                "Level {}, {} apples",
+                                save.current_level, save.golden_apples
+                            ))
+                            .on_hover_text(format!(
+                                "{} levels completed in {} minutes",
+                                save.stats.levels_completed,
+                                (save.stats.play_time / 60.0) as i32
+                            ));
+                            let current = profiles.active.as_ref() == Some(name);
+                            if nav.button(ui, !current, "Select") {
+                                action = Some(ProfileAction::Select(name.clone()));
+                            }
+                            if nav.button(ui, true, "Rename") {
+                                profiles.renaming = Some((name.clone(), name.clone()));
+                            }
+                            if nav.button(ui, true, "Duplicate") {
+                                action = Some(ProfileAction::Duplicate(name.clone()));
+                            }
+                        }
+                        Err(e) => {
+                            ui.label("Corrupt").on_hover_text(e);
+                        }
+                    }
+                    if profiles.deleting.as_ref() == Some(name) {
+                        if nav.button(ui, true, "Really delete") {
+                            action = Some(ProfileAction::Delete(name.clone()));
+                        }
+                    } else if nav.button(ui, true, "Delete") {
+                        profiles.deleting = Some(name.clone());
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut profiles.new_name);
+                if nav.button(ui, true, "New profile") {
+                    action = Some(ProfileAction::Create(profiles.new_name.clone()));
+                }
+            });
+            if let Some(error) = &profiles.error {
+                ui.label(error);
+            }
+            if nav.button(ui, true, "Back") {
+                profiles.menu_open = false;
+            }
+        });
+
+    if let Some(action) = action {
+        profiles.error = None;
+        match profiles.run(action, &mut data) {
+            Ok(()) => profiles.refresh(),
+            Err(e) => profiles.error = Some(e),
+        }
+    }
+}
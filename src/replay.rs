@@ -0,0 +1,359 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aim::Aim,
+    console::Cheats,
+    input::{Action, ActionState},
+    menu::MenuNav,
+    profiles::Profiles,
+    save::{self, GameData, SaveData},
+    shop::Upgrades,
+    tick::{GameClock, TICK},
+    title::TitleMenu,
+    tuning::Tuning,
+    GameState, Info, Progress,
+};
+
+/// Bump this whenever the simulation changes, older replays would play out differently.
+pub const REPLAY_VERSION: u32 = 5;
+
+const SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+/// What the player does during one tick, all the gameplay reads of their input.
+#[derive(Resource, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct TickInput {
+    /// Up to unit length, with the pull toward the cursor while clicking.
+    pub movement: Vec2,
+    pub aim: Vec2,
+    pub dash: bool,
+    pub magnet: bool,
+    pub dynamite: bool,
+    pub aimed_dash: bool,
+    pub aimed_magnet: bool,
+    pub aimed_dynamite: bool,
+}
+
+/// A level attempt, enough to play it out again tick by tick.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub level: u32,
+    pub seed: u64,
+    pub upgrades: Upgrades,
+    /// The tuning can change between sessions, the replay plays with its own.
+    pub tuning: Tuning,
+    /// Fingerprint of the difficulty band the level was generated with, the seed alone
+    /// gives another layout once the band changes.
+    pub difficulty: u64,
+    pub ticks: u32,
+    /// Run length encoded, how many ticks in a row had the same input.
+    inputs: Vec<(u32, TickInput)>,
+}
+
+impl Replay {
+    fn new(level: u32, seed: u64, upgrades: Upgrades, tuning: Tuning, difficulty: u64) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            level,
+            seed,
+            upgrades,
+            tuning,
+            difficulty,
+            ticks: 0,
+            inputs: vec![],
+        }
+    }
+
+    fn push(&mut self, input: TickInput) {
+        self.ticks += 1;
+        match self.inputs.last_mut() {
+            Some((count, last)) if *last == input => *count += 1,
+            _ => self.inputs.push((1, input)),
+        }
+    }
+
+    pub fn input(&self, tick: u32) -> TickInput {
+        let mut start = 0;
+        for (count, input) in self.inputs.iter() {
+            start += count;
+            if tick < start {
+                return *input;
+            }
+        }
+        TickInput::default()
+    }
+}
+
+/// The attempt being played right now.
+#[derive(Resource, Default)]
+pub struct Recorder(pub Option<Replay>);
+
+#[derive(Resource, Default)]
+pub struct ReplayViewer {
+    replay: Option<Replay>,
+    /// The profile as it was before watching, the replay plays over it.
+    stash: Option<SaveData>,
+    /// The profile set up like when the replay was recorded, to start over from.
+    base: Option<SaveData>,
+    /// The tuning in use before watching, it's put back after.
+    pub tuning: Option<Tuning>,
+    list: Option<Vec<(u32, Result<Replay, String>)>>,
+}
+
+impl ReplayViewer {
+    pub fn watching(&self) -> bool {
+        self.replay.is_some()
+    }
+}
+
+pub fn tick_input(
+    mut clock: ResMut<GameClock>,
+    mut input: ResMut<TickInput>,
+    mut recorder: ResMut<Recorder>,
+    viewer: Res<ReplayViewer>,
+    actions: Res<ActionState>,
+    aim: Res<Aim>,
+    mouse_input: Res<Input<MouseButton>>,
+) {
+    let tick = clock.tick;
+    clock.tick += 1;
+    if let Some(replay) = &viewer.replay {
+        *input = replay.input(tick);
+        return;
+    }
+    let mut movement = actions.movement;
+    if mouse_input.pressed(MouseButton::Left) {
+        movement += aim.to_cursor;
+    }
+    *input = TickInput {
+        movement: movement.clamp_length_max(1.0),
+        aim: aim.dir,
+        dash: actions.pressed(Action::Dash),
+        magnet: actions.pressed(Action::Magnet),
+        dynamite: actions.pressed(Action::Dynamite),
+        aimed_dash: aim.dash,
+        aimed_magnet: aim.magnet,
+        aimed_dynamite: aim.dynamite,
+    };
+    if let Some(replay) = &mut recorder.0 {
+        replay.push(*input);
+    }
+}
+
+pub fn start_recording(
+    mut recorder: ResMut<Recorder>,
+    mut info: ResMut<Info>,
+    prog: Res<Progress>,
+    upgrades: Res<Upgrades>,
+    cheats: Res<Cheats>,
+    tuning: Res<Tuning>,
+) {
+    if cheats.active() {
+        recorder.0 = None;
+        return;
+    }
+    let level = info.get_level(&prog);
+    recorder.0 = Some(Replay::new(
+        level.id,
+        level.seed,
+        upgrades.clone(),
+        tuning.clone(),
+        fingerprint(info.difficulty.band(level.id)),
+    ));
+}
+
+/// A replay plays with the tuning it started with, after a hot reload or an edit in the
+/// inspector the rest of the attempt would play out differently, so it isn't kept.
+pub fn drop_stale_recording(tuning: Res<Tuning>, mut recorder: ResMut<Recorder>) {
+    if !tuning.is_changed() {
+        return;
+    }
+    let stale = recorder
+        .0
+        .as_ref()
+        .is_some_and(|replay| fingerprint(&replay.tuning) != fingerprint(&*tuning));
+    if stale {
+        info!("the tuning changed, this attempt isn't recorded");
+        recorder.0 = None;
+    }
+}
+
+/// A short, stable summary of the rules a run was played with, to tell when they changed.
+pub fn fingerprint<T: Serialize>(value: &T) -> u64 {
+    // FNV-1a, the std hasher may change between builds
+    ron::to_string(value)
+        .unwrap_or_default()
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+pub fn save_recording(mut recorder: ResMut<Recorder>, profiles: Res<Profiles>) {
+    if let (Some(replay), Some(name)) = (recorder.0.take(), &profiles.active) {
+        if replay.ticks > 0 {
+            if let Err(e) = save::write_replay(name, &replay) {
+                warn!("replay not saved, {}", e);
+            }
+        }
+    }
+}
+
+pub fn limit_replay(viewer: Res<ReplayViewer>, mut clock: ResMut<GameClock>) {
+    clock.limit = viewer.replay.as_ref().map(|r| r.ticks);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn replay_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut title: ResMut<TitleMenu>,
+    mut viewer: ResMut<ReplayViewer>,
+    profiles: Res<Profiles>,
+    mut data: GameData,
+    mut state: ResMut<State<GameState>>,
+    mut nav: ResMut<MenuNav>,
+    mut tuning: ResMut<Tuning>,
+) {
+    if !title.replays_open {
+        viewer.list = None;
+        return;
+    }
+    let viewer = &mut *viewer;
+    let list = viewer.list.get_or_insert_with(|| {
+        let (name, levels) = match (&profiles.active, profiles.active_save()) {
+            (Some(name), Some(save)) => (name, save.current_level),
+            _ => return vec![],
+        };
+        (1..=levels)
+            .filter_map(|level| match save::read_replay(name, level) {
+                Ok(Some(replay)) if replay.difficulty != data.difficulty(level) => Some((
+                    level,
+                    Err("The level generation changed since it was recorded".to_string()),
+                )),
+                Ok(Some(replay)) => Some((level, Ok(replay))),
+                Ok(None) => None,
+                Err(save::SaveError::Version(_)) => Some((
+                    level,
+                    Err("Recorded with another version of the game".to_string()),
+                )),
+                Err(e) => Some((level, Err(e.to_string()))),
+            })
+            .collect()
+    });
+    let mut watch = None;
+    egui::Window::new("Replays")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::splat(0.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            if list.is_empty() {
+                ui.label("No replays yet, the last attempt at each level is kept");
+            }
+            egui::Grid::new("ReplayList").show(ui, |ui| {
+                for (level, replay) in list.iter() {
+                    ui.label(format!("Level {}", level));
+                    match replay {
+                        Ok(replay) => {
+                            ui.label(format!("{:.1}s", replay.ticks as f32 * TICK));
+                            if nav.button(ui, true, "Watch") {
+                                watch = Some(replay.clone());
+                            }
+                        }
+                        Err(e) => {
+                            ui.label("Incompatible").on_hover_text(e);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+            if nav.button(ui, true, "Back") {
+                title.replays_open = false;
+            }
+        });
+
+    if let Some(replay) = watch {
+        let stash = data.capture();
+        let mut base = stash.clone();
+        base.current_level = replay.level;
+        base.level_seeds.insert(replay.level, replay.seed);
+        base.upgrades = replay.upgrades.clone();
+        data.apply(base.clone());
+        viewer.tuning = Some(std::mem::replace(&mut *tuning, replay.tuning.clone()));
+        viewer.replay = Some(replay);
+        viewer.stash = Some(stash);
+        viewer.base = Some(base);
+        state.set(GameState::LevelLoading).ok();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn replay_controls(
+    mut egui_context: ResMut<EguiContext>,
+    mut viewer: ResMut<ReplayViewer>,
+    mut clock: ResMut<GameClock>,
+    mut actions: ResMut<ActionState>,
+    mut data: GameData,
+    mut state: ResMut<State<GameState>>,
+    mut nav: ResMut<MenuNav>,
+    mut tuning: ResMut<Tuning>,
+) {
+    let (level, ticks) = match &viewer.replay {
+        Some(replay) => (replay.level, replay.ticks),
+        None => return,
+    };
+    let mut exit = actions.consume(Action::Pause);
+    let mut seek = None;
+    egui::Window::new("ReplayControls")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -20.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(format!(
+                "Replay of level {}, {:.1}s / {:.1}s",
+                level,
+                clock.tick as f32 * TICK,
+                ticks as f32 * TICK
+            ));
+            ui.horizontal(|ui| {
+                let text = if clock.paused { "Play" } else { "Pause" };
+                if nav.button(ui, true, text) {
+                    clock.paused = !clock.paused;
+                }
+                for speed in SPEEDS {
+                    if nav.button(ui, clock.speed != speed, format!("{}x", speed)) {
+                        clock.speed = speed;
+                    }
+                }
+            });
+            let mut tick = clock.seek.unwrap_or(clock.tick);
+            let timeline = ui.add(egui::Slider::new(&mut tick, 0..=ticks).show_value(false));
+            if timeline.changed() {
+                seek = Some(tick);
+            }
+            exit |= nav.button(ui, true, "Exit");
+        });
+
+    if exit {
+        if let Some(stash) = viewer.stash.take() {
+            data.apply(stash);
+        }
+        if let Some(stash) = viewer.tuning.take() {
+            *tuning = stash;
+        }
+        viewer.replay = None;
+        viewer.base = None;
+        *clock = GameClock::default();
+        state.replace(GameState::Title).ok();
+    } else if let Some(tick) = seek {
+        clock.seek = Some(tick);
+        if tick < clock.tick {
+            // the simulation only runs forward, going back means starting over
+            if let Some(base) = &viewer.base {
+                data.apply(base.clone());
+            }
+            state.replace(GameState::LevelLoading).ok();
+        }
+    }
+}
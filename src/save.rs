@@ -0,0 +1,359 @@
+use std::{collections::HashMap, fmt, marker::PhantomData};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ghost::Ghosts,
+    profiles::Profiles,
+    replay::{self, Replay, REPLAY_VERSION},
+    settings::Settings,
+    shop::Upgrades,
+    Info, Progress, Stats,
+};
+
+/// Bump this whenever [`SaveData`] changes shape and teach [`migrate`] the old one.
+pub const SAVE_VERSION: u32 = 3;
+
+const INDEX_KEY: &str = "ld52_profiles";
+/// Where the single save of version 1 lived, before there were profiles.
+const LEGACY_KEY: &str = "ld52_save";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SaveData {
+    version: u32,
+    pub current_level: u32,
+    pub golden_apples: i32,
+    pub upgrades: Upgrades,
+    pub level_seeds: HashMap<u32, u64>,
+    best_times: HashMap<u32, f32>,
+    settings: Settings,
+    pub stats: Stats,
+    /// Version 3 added the ghosts, older saves have none.
+    #[serde(default)]
+    ghosts: Ghosts,
+}
+
+#[derive(Deserialize)]
+struct SaveDataV1 {
+    current_level: u32,
+    golden_apples: i32,
+    upgrades: Upgrades,
+    level_seeds: HashMap<u32, u64>,
+    best_times: HashMap<u32, f32>,
+}
+
+/// Just enough of a save to know how to read the rest of it.
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Storage(String),
+    Format(String),
+    Version(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Storage(e) => write!(f, "could not access the save: {}", e),
+            SaveError::Format(e) => write!(f, "the save is corrupt: {}", e),
+            SaveError::Version(v) => write!(f, "the save has unknown version {}", v),
+        }
+    }
+}
+
+/// Everything that belongs to a profile.
+#[derive(SystemParam)]
+pub struct GameData<'w, 's> {
+    info: ResMut<'w, Info>,
+    prog: ResMut<'w, Progress>,
+    upgrades: ResMut<'w, Upgrades>,
+    settings: ResMut<'w, Settings>,
+    stats: ResMut<'w, Stats>,
+    ghosts: ResMut<'w, Ghosts>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> GameData<'w, 's> {
+    pub fn capture(&self) -> SaveData {
+        SaveData {
+            version: SAVE_VERSION,
+            current_level: self.prog.current_level,
+            golden_apples: self.prog.golden_apples,
+            upgrades: self.upgrades.clone(),
+            level_seeds: self.info.seeds.clone(),
+            best_times: self.prog.best_times.clone(),
+            settings: self.settings.clone(),
+            stats: self.stats.clone(),
+            ghosts: self.ghosts.clone(),
+        }
+    }
+
+    /// Fingerprint of the difficulty band the level is generated with.
+    pub fn difficulty(&self, level: u32) -> u64 {
+        replay::fingerprint(self.info.difficulty.band(level))
+    }
+
+    /// Starts the profile over, the settings and lifetime stats are kept.
+    pub fn new_game(&mut self) {
+        self.apply(SaveData {
+            settings: self.settings.clone(),
+            stats: self.stats.clone(),
+            ..default()
+        });
+    }
+
+    pub fn apply(&mut self, data: SaveData) {
+        *self.prog = Progress::default();
+        self.prog.current_level = data.current_level;
+        self.prog.golden_apples = data.golden_apples;
+        self.prog.best_times = data.best_times;
+        *self.upgrades = data.upgrades;
+        *self.settings = data.settings;
+        *self.stats = data.stats;
+        *self.ghosts = data.ghosts;
+        // the difficulty table comes from the assets, not from the profile
+        self.info.levels.clear();
+        self.info.seeds = data.level_seeds;
+        let level = self.info.get_level(&self.prog);
+        self.prog.level_timer.set_duration(level.duration);
+        self.prog.level_timer.reset();
+    }
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        Self {
+            version: SAVE_VERSION,
+            current_level: 1,
+            golden_apples: 0,
+            upgrades: Upgrades::default(),
+            level_seeds: HashMap::new(),
+            best_times: HashMap::new(),
+            settings: Settings::default(),
+            stats: Stats::default(),
+            ghosts: Ghosts::default(),
+        }
+    }
+}
+
+impl SaveData {
+    fn to_text(&self) -> Result<String, SaveError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| SaveError::Format(e.to_string()))
+    }
+
+    /// Reads a save of any known version.
+    pub fn from_text(text: &str) -> Result<SaveData, SaveError> {
+        let header: SaveHeader = parse(text)?;
+        migrate(header.version, text)
+    }
+}
+
+fn parse<'a, T: Deserialize<'a>>(text: &'a str) -> Result<T, SaveError> {
+    ron::from_str(text).map_err(|e| SaveError::Format(e.to_string()))
+}
+
+/// Reads a save written by any known version, upgrading it to the current layout.
+fn migrate(version: u32, text: &str) -> Result<SaveData, SaveError> {
+    match version {
+        1 => {
+            let v1: SaveDataV1 = parse(text)?;
+            Ok(SaveData {
+                version: SAVE_VERSION,
+                current_level: v1.current_level,
+                golden_apples: v1.golden_apples,
+                upgrades: v1.upgrades,
+                level_seeds: v1.level_seeds,
+                best_times: v1.best_times,
+                settings: Settings::default(),
+                stats: Stats::default(),
+                ghosts: Ghosts::default(),
+            })
+        }
+        2 => parse(text).map(|data| SaveData {
+            version: SAVE_VERSION,
+            ..data
+        }),
+        SAVE_VERSION => parse(text),
+        v => Err(SaveError::Version(v)),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod storage {
+    use std::{fs, path::PathBuf};
+
+    fn path(key: &str) -> PathBuf {
+        PathBuf::from("saves").join(format!("{}.ron", key))
+    }
+
+    pub fn read(key: &str) -> Result<Option<String>, String> {
+        match fs::read_to_string(path(key)) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    pub fn write(key: &str, text: &str) -> Result<(), String> {
+        let path = path(key);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        // write then rename, so a crash mid-write never leaves half a save behind
+        let tmp = path.with_extension("ron.tmp");
+        fs::write(&tmp, text).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, &path).map_err(|e| e.to_string())
+    }
+
+    pub fn remove(key: &str) -> Result<(), String> {
+        match fs::remove_file(path(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod storage {
+    fn local_storage() -> Result<web_sys::Storage, String> {
+        web_sys::window()
+            .ok_or("no window")?
+            .local_storage()
+            .map_err(|_| "localStorage is not accessible")?
+            .ok_or_else(|| "localStorage is not available".to_string())
+    }
+
+    pub fn read(key: &str) -> Result<Option<String>, String> {
+        local_storage()?
+            .get_item(key)
+            .map_err(|_| "could not read from localStorage".to_string())
+    }
+
+    pub fn write(key: &str, text: &str) -> Result<(), String> {
+        local_storage()?
+            .set_item(key, text)
+            .map_err(|_| "could not write to localStorage".to_string())
+    }
+
+    pub fn remove(key: &str) -> Result<(), String> {
+        local_storage()?
+            .remove_item(key)
+            .map_err(|_| "could not remove from localStorage".to_string())
+    }
+}
+
+fn profile_key(name: &str) -> String {
+    format!("ld52_profile_{}", name)
+}
+
+pub fn read_profile(name: &str) -> Result<SaveData, SaveError> {
+    match storage::read(&profile_key(name)).map_err(SaveError::Storage)? {
+        Some(text) => SaveData::from_text(&text),
+        None => Err(SaveError::Storage("the profile has no save".to_string())),
+    }
+}
+
+pub fn write_profile(name: &str, data: &SaveData) -> Result<(), SaveError> {
+    storage::write(&profile_key(name), &data.to_text()?).map_err(SaveError::Storage)
+}
+
+pub fn remove_profile(name: &str) -> Result<(), SaveError> {
+    storage::remove(&profile_key(name)).map_err(SaveError::Storage)
+}
+
+fn replay_key(profile: &str, level: u32) -> String {
+    format!("ld52_replay_{}_{}", profile, level)
+}
+
+/// The last attempt at a level.
+pub fn read_replay(profile: &str, level: u32) -> Result<Option<Replay>, SaveError> {
+    match storage::read(&replay_key(profile, level)).map_err(SaveError::Storage)? {
+        Some(text) => parse_replay(&text).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Replays from other versions of the game can't be played, they'd play out differently.
+pub fn parse_replay(text: &str) -> Result<Replay, SaveError> {
+    let header: SaveHeader = parse(text)?;
+    if header.version != REPLAY_VERSION {
+        return Err(SaveError::Version(header.version));
+    }
+    parse(text)
+}
+
+pub fn write_replay(profile: &str, replay: &Replay) -> Result<(), SaveError> {
+    let text = ron::to_string(replay).map_err(|e| SaveError::Format(e.to_string()))?;
+    storage::write(&replay_key(profile, replay.level), &text).map_err(SaveError::Storage)
+}
+
+/// Moves the replays of a profile to another name, or deletes them without one.
+pub fn move_replays(profile: &str, to: Option<&str>, levels: u32) -> Result<(), SaveError> {
+    if let Some(to) = to {
+        copy_replays(profile, to, levels)?;
+    }
+    for level in 1..=levels {
+        storage::remove(&replay_key(profile, level)).map_err(SaveError::Storage)?;
+    }
+    Ok(())
+}
+
+/// Gives another profile the replays of this one, for a duplicate.
+pub fn copy_replays(profile: &str, to: &str, levels: u32) -> Result<(), SaveError> {
+    for level in 1..=levels {
+        if let Some(text) =
+            storage::read(&replay_key(profile, level)).map_err(SaveError::Storage)?
+        {
+            storage::write(&replay_key(to, level), &text).map_err(SaveError::Storage)?;
+        }
+    }
+    Ok(())
+}
+
+/// The names of every profile. A save from before profiles becomes the first one.
+pub fn read_index() -> Result<Vec<String>, SaveError> {
+    match storage::read(INDEX_KEY).map_err(SaveError::Storage)? {
+        Some(text) => parse(&text),
+        None => match storage::read(LEGACY_KEY).map_err(SaveError::Storage)? {
+            Some(text) => {
+                let name = "Player".to_string();
+                write_profile(&name, &SaveData::from_text(&text)?)?;
+                let names = vec![name];
+                write_index(&names)?;
+                storage::remove(LEGACY_KEY).map_err(SaveError::Storage)?;
+                Ok(names)
+            }
+            None => Ok(vec![]),
+        },
+    }
+}
+
+pub fn write_index(names: &[String]) -> Result<(), SaveError> {
+    let text = ron::to_string(names).map_err(|e| SaveError::Format(e.to_string()))?;
+    storage::write(INDEX_KEY, &text).map_err(SaveError::Storage)
+}
+
+/// Send this to write the active profile.
+pub struct SaveGame;
+
+pub fn request_save(mut save: EventWriter<SaveGame>) {
+    save.send(SaveGame);
+}
+
+pub fn save_game(mut requests: EventReader<SaveGame>, profiles: Res<Profiles>, data: GameData) {
+    if requests.iter().count() == 0 {
+        return;
+    }
+    if let Some(name) = &profiles.active {
+        if let Err(e) = write_profile(name, &data.capture()) {
+            warn!("progress not saved, {}", e);
+        }
+    }
+}
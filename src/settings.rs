@@ -0,0 +1,146 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aim::AbilityModes,
+    audio::Volumes,
+    input::{self, Action, Bindings},
+    menu::MenuNav,
+    save::SaveGame,
+};
+
+#[derive(Resource, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub screen_effects: bool,
+    /// No camera shake or zoom.
+    pub reduce_motion: bool,
+    /// The level ends when its timer runs out, instead of only costing the golden apple.
+    pub hard_fail: bool,
+    pub bindings: Bindings,
+    pub ability_modes: AbilityModes,
+    pub volumes: Volumes,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            screen_effects: true,
+            reduce_motion: false,
+            hard_fail: false,
+            bindings: Bindings::default(),
+            ability_modes: AbilityModes::default(),
+            volumes: Volumes::default(),
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct SettingsMenu {
+    pub open: bool,
+    rebinding: Option<Action>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn settings_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut menu: ResMut<SettingsMenu>,
+    mut settings: ResMut<Settings>,
+    mut save: EventWriter<SaveGame>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut nav: ResMut<MenuNav>,
+) {
+    if !menu.open {
+        return;
+    }
+    if let Some(action) = menu.rebinding {
+        if let Some(binding) =
+            input::pressed_binding(&keyboard_input, &mouse_input, &gamepad_buttons)
+        {
+            settings.bindings.rebind(action, binding);
+            menu.rebinding = None;
+        }
+    }
+    egui::Window::new("Settings")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::splat(0.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            let effects = if settings.screen_effects { "on" } else { "off" };
+            if nav.button(ui, true, format!("Screen effects: {}", effects)) {
+                settings.screen_effects = !settings.screen_effects;
+            }
+            let motion = if settings.reduce_motion { "on" } else { "off" };
+            if nav.button(ui, true, format!("Reduce screen motion: {}", motion)) {
+                settings.reduce_motion = !settings.reduce_motion;
+            }
+            let hard_fail = if settings.hard_fail { "on" } else { "off" };
+            if nav.button(ui, true, format!("Fail when time runs out: {}", hard_fail)) {
+                settings.hard_fail = !settings.hard_fail;
+            }
+            egui::Grid::new("Volumes").show(ui, |ui| {
+                let volumes = &mut settings.volumes;
+                for (name, volume) in [
+                    ("Master volume", &mut volumes.master),
+                    ("Music", &mut volumes.music),
+                    ("Sound effects", &mut volumes.sfx),
+                ] {
+                    ui.label(name);
+                    // steps of a tenth, so the pad can reach every value
+                    let step = (*volume * 10.0).round();
+                    if nav.button(ui, step > 0.0, "-") {
+                        *volume = (step - 1.0) / 10.0;
+                    }
+                    ui.label(format!("{:.0}%", *volume * 100.0));
+                    if nav.button(ui, step < 10.0, "+") {
+                        *volume = (step + 1.0) / 10.0;
+                    }
+                    ui.end_row();
+                }
+            });
+            egui::Grid::new("AbilityModes").show(ui, |ui| {
+                let modes = &mut settings.ability_modes;
+                for (name, mode) in [
+                    ("Dash", &mut modes.dash),
+                    ("Magnet", &mut modes.magnet),
+                    ("Dynamite", &mut modes.dynamite),
+                ] {
+                    ui.label(name);
+                    if nav.button(ui, true, format!("{:?}", mode)) {
+                        *mode = mode.toggled();
+                    }
+                    ui.end_row();
+                }
+            });
+            egui::Grid::new("Controls").show(ui, |ui| {
+                for action in Action::ALL {
+                    ui.label(action.name());
+                    if menu.rebinding == Some(action) {
+                        ui.label("Press a key or button...");
+                        if nav.button(ui, true, "Cancel") {
+                            menu.rebinding = None;
+                        }
+                    } else {
+                        ui.label(settings.bindings.prompt(action));
+                        if nav.button(ui, true, "Rebind") {
+                            menu.rebinding = Some(action);
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.horizontal(|ui| {
+                if nav.button(ui, true, "Back") {
+                    menu.open = false;
+                    menu.rebinding = None;
+                    save.send(SaveGame);
+                }
+                if nav.button(ui, true, "Default controls") {
+                    settings.bindings = Bindings::default();
+                }
+            });
+        });
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dialogue::DialogueTrigger,
+    input::Action,
+    menu::MenuNav,
+    settings::{Settings, SettingsMenu},
+    tuning::Tuning,
+    GameState, Progress,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Upgrade {
+    Dash,
+    DashCooldown,
+    DashBoost,
+    Magnet,
+    MagnetRadius,
+    MagnetPower,
+    Dynamite,
+    DynamiteRadius,
+    Acceleration,
+    Mass,
+}
+
+struct UpgradeInfo {
+    name: &'static str,
+    description: &'static str,
+    parent: Option<Upgrade>,
+    max_level: u32,
+}
+
+impl Upgrade {
+    // roots first, each followed by its children: this is the order of the shop
+    pub const ALL: [Upgrade; 10] = [
+        Upgrade::Dash,
+        Upgrade::DashCooldown,
+        Upgrade::DashBoost,
+        Upgrade::Magnet,
+        Upgrade::MagnetRadius,
+        Upgrade::MagnetPower,
+        Upgrade::Dynamite,
+        Upgrade::DynamiteRadius,
+        Upgrade::Acceleration,
+        Upgrade::Mass,
+    ];
+
+    /// The action that triggers an ability, if the upgrade unlocks one.
+    pub fn action(&self) -> Option<Action> {
+        match self {
+            Upgrade::Dash => Some(Action::Dash),
+            Upgrade::Magnet => Some(Action::Magnet),
+            Upgrade::Dynamite => Some(Action::Dynamite),
+            _ => None,
+        }
+    }
+
+    /// Price of the first level, the abilities are priced in the tuning.
+    fn base_cost(&self, tuning: &Tuning) -> i32 {
+        match self {
+            Upgrade::Dash => tuning.dash_cost,
+            Upgrade::DashCooldown => 2,
+            Upgrade::DashBoost => 2,
+            Upgrade::Magnet => tuning.attract_cost,
+            Upgrade::MagnetRadius => 3,
+            Upgrade::MagnetPower => 3,
+            Upgrade::Dynamite => tuning.explode_cost,
+            Upgrade::DynamiteRadius => 4,
+            Upgrade::Acceleration => 1,
+            Upgrade::Mass => 1,
+        }
+    }
+
+    fn info(&self) -> UpgradeInfo {
+        match self {
+            Upgrade::Dash => UpgradeInfo {
+                name: "Dash",
+                description: "A short burst of speed",
+                parent: None,
+                max_level: 1,
+            },
+            Upgrade::DashCooldown => UpgradeInfo {
+                name: "Quick dash",
+                description: "Dash more often",
+                parent: Some(Upgrade::Dash),
+                max_level: 3,
+            },
+            Upgrade::DashBoost => UpgradeInfo {
+                name: "Strong dash",
+                description: "Dash further",
+                parent: Some(Upgrade::Dash),
+                max_level: 3,
+            },
+            Upgrade::Magnet => UpgradeInfo {
+                name: "Magnet",
+                description: "Pull the blocks around you",
+                parent: None,
+                max_level: 1,
+            },
+            Upgrade::MagnetRadius => UpgradeInfo {
+                name: "Wide magnet",
+                description: "Reach blocks further away",
+                parent: Some(Upgrade::Magnet),
+                max_level: 3,
+            },
+            Upgrade::MagnetPower => UpgradeInfo {
+                name: "Strong magnet",
+                description: "Pull harder",
+                parent: Some(Upgrade::Magnet),
+                max_level: 3,
+            },
+            Upgrade::Dynamite => UpgradeInfo {
+                name: "Dynamite",
+                description: "Blow the blocks around you away",
+                parent: None,
+                max_level: 1,
+            },
+            Upgrade::DynamiteRadius => UpgradeInfo {
+                name: "Big dynamite",
+                description: "A wider blast",
+                parent: Some(Upgrade::Dynamite),
+                max_level: 3,
+            },
+            Upgrade::Acceleration => UpgradeInfo {
+                name: "Swift feet",
+                description: "Accelerate faster",
+                parent: None,
+                max_level: 5,
+            },
+            Upgrade::Mass => UpgradeInfo {
+                name: "Heavy bones",
+                description: "Ram blocks harder",
+                parent: None,
+                max_level: 5,
+            },
+        }
+    }
+}
+
+#[derive(Resource, Default, Clone, Serialize, Deserialize)]
+pub struct Upgrades {
+    levels: HashMap<Upgrade, u32>,
+}
+
+impl Upgrades {
+    pub fn level(&self, upgrade: Upgrade) -> u32 {
+        *self.levels.get(&upgrade).unwrap_or(&0)
+    }
+
+    pub fn has(&self, upgrade: Upgrade) -> bool {
+        self.level(upgrade) > 0
+    }
+
+    /// Price of the next level of the upgrade, every level costs more than the last.
+    pub fn cost(&self, upgrade: Upgrade, tuning: &Tuning) -> i32 {
+        upgrade.base_cost(tuning) * (self.level(upgrade) as i32 + 1)
+    }
+
+    pub fn available(&self, upgrade: Upgrade) -> bool {
+        let info = upgrade.info();
+        self.level(upgrade) < info.max_level && info.parent.is_none_or(|p| self.has(p))
+    }
+
+    pub fn buy(&mut self, upgrade: Upgrade, prog: &mut Progress, tuning: &Tuning) -> bool {
+        let cost = self.cost(upgrade, tuning);
+        if !self.available(upgrade) || prog.golden_apples < cost {
+            return false;
+        }
+        prog.golden_apples -= cost;
+        *self.levels.entry(upgrade).or_insert(0) += 1;
+        true
+    }
+
+    pub fn dash_cooldown(&self, tuning: &Tuning) -> f32 {
+        tuning.character.dash_cooldown * 0.8f32.powi(self.level(Upgrade::DashCooldown) as i32)
+    }
+
+    pub fn dash_boost(&self, tuning: &Tuning) -> f32 {
+        tuning.dash_boost + 0.5 * self.level(Upgrade::DashBoost) as f32
+    }
+
+    pub fn attract_radius(&self, tuning: &Tuning) -> f32 {
+        tuning.attract_radius + 25.0 * self.level(Upgrade::MagnetRadius) as f32
+    }
+
+    pub fn attract_power(&self, tuning: &Tuning) -> f32 {
+        tuning.attract_power * (1.0 + 0.3 * self.level(Upgrade::MagnetPower) as f32)
+    }
+
+    pub fn explode_radius(&self, tuning: &Tuning) -> f32 {
+        tuning.explode_radius + 30.0 * self.level(Upgrade::DynamiteRadius) as f32
+    }
+
+    pub fn acceleration(&self, tuning: &Tuning) -> f32 {
+        tuning.acceleration * (1.0 + 0.15 * self.level(Upgrade::Acceleration) as f32)
+    }
+
+    pub fn character_density(&self) -> f32 {
+        1.0 + 0.5 * self.level(Upgrade::Mass) as f32
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn shop(
+    mut egui_context: ResMut<EguiContext>,
+    mut upgrades: ResMut<Upgrades>,
+    mut prog: ResMut<Progress>,
+    mut state: ResMut<State<GameState>>,
+    mut settings_menu: ResMut<SettingsMenu>,
+    settings: Res<Settings>,
+    mut nav: ResMut<MenuNav>,
+    mut dialogue: EventWriter<DialogueTrigger>,
+    tuning: Res<Tuning>,
+) {
+    if settings_menu.open {
+        return;
+    }
+    egui::Window::new("Shop")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::splat(0.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(format!("You have {} golden apples", prog.golden_apples));
+            egui::Grid::new("Upgrades").show(ui, |ui| {
+                for upgrade in Upgrade::ALL {
+                    let info = upgrade.info();
+                    if info.parent.is_some_and(|p| !upgrades.has(p)) {
+                        continue;
+                    }
+                    let name = if let Some(action) = upgrade.action() {
+                        format!("{} {}", info.name, settings.bindings.prompt(action))
+                    } else if info.parent.is_some() {
+                        format!("  {}", info.name)
+                    } else {
+                        info.name.to_string()
+                    };
+                    ui.label(name).on_hover_text(info.description);
+                    ui.label(format!("{}/{}", upgrades.level(upgrade), info.max_level));
+                    if upgrades.level(upgrade) < info.max_level {
+                        let cost = upgrades.cost(upgrade, &tuning);
+                        let can_buy = upgrades.available(upgrade) && prog.golden_apples >= cost;
+                        if nav.button(ui, can_buy, format!("Buy ({})", cost)) {
+                            // only the abilities have an unlock beat, not their upgrades
+                            let unlock = !upgrades.has(upgrade)
+                                && matches!(
+                                    upgrade,
+                                    Upgrade::Dash | Upgrade::Magnet | Upgrade::Dynamite
+                                );
+                            if upgrades.buy(upgrade, &mut prog, &tuning) && unlock {
+                                dialogue.send(DialogueTrigger::Unlock(upgrade));
+                            }
+                        }
+                    } else {
+                        ui.label("Max");
+                    }
+                    ui.end_row();
+                }
+            });
+            ui.horizontal(|ui| {
+                if nav.button(ui, true, "Next level") {
+                    state.set(GameState::LevelLoading).ok();
+                }
+                if nav.button(ui, true, "Settings") {
+                    settings_menu.open = true;
+                }
+            });
+        });
+}
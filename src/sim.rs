@@ -0,0 +1,536 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    aim::Aim,
+    attract, check_finish,
+    console::{self, Cheats},
+    dialogue::DialogueTrigger,
+    explode,
+    ghost::{self, GhostRun, Ghosts},
+    input::ActionState,
+    mass_increase, movement, pause,
+    replay::{self, Recorder, ReplayViewer, TickInput},
+    settings::Settings,
+    setup_level, setup_physics,
+    shop::Upgrades,
+    start_level, teardown_level,
+    tick::{self, GameTick, TickPlugin, TickStage},
+    tuning::Tuning,
+    AbilityUsed, GameState, Info, Progress, Stats,
+};
+
+/// The level itself: loading and tearing it down, the fixed tick and the physics.
+/// Nothing here draws or plays sounds, so it runs the same in the game and headless,
+/// the app only has to provide `GameAssets`, `ClearColor` and `ChromaticAberration`.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Info>()
+            .init_resource::<Upgrades>()
+            .init_resource::<Progress>()
+            .init_resource::<Stats>()
+            .init_resource::<Settings>()
+            .init_resource::<ActionState>()
+            .init_resource::<Aim>()
+            .init_resource::<TickInput>()
+            .init_resource::<Recorder>()
+            .init_resource::<Ghosts>()
+            .init_resource::<GhostRun>()
+            .init_resource::<ReplayViewer>()
+            .init_resource::<Tuning>()
+            .init_resource::<Cheats>()
+            .add_event::<DialogueTrigger>()
+            .add_event::<AbilityUsed>()
+            .add_plugin(
+                RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0)
+                    .with_default_system_setup(false),
+            )
+            .add_plugin(TickPlugin)
+            .add_startup_system(setup_physics)
+            .add_system_set(SystemSet::on_update(GameState::LevelLoading).with_system(setup_level))
+            .add_system_set(
+                SystemSet::on_update(GameState::LevelLoading)
+                    .with_system(start_level.after(setup_level)),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Gameplay).with_system(tick::reset_clock))
+            .add_system_set(
+                SystemSet::on_enter(GameState::Gameplay).with_system(ghost::start_ghost),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Gameplay).with_system(teardown_level))
+            .add_system_set(
+                SystemSet::on_exit(GameState::Gameplay).with_system(ghost::finish_ghost),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Gameplay).with_system(console::reset_cheats),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Replay).with_system(tick::reset_clock))
+            .add_system_set(
+                SystemSet::on_enter(GameState::Replay).with_system(replay::limit_replay),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Replay).with_system(teardown_level))
+            // one after the other, the same inputs have to give the same outcome
+            .stage(GameTick, |schedule: &mut Schedule| {
+                schedule
+                    .add_system_to_stage(TickStage::Input, replay::drop_stale_recording)
+                    .add_system_to_stage(TickStage::Input, ghost::drop_stale_ghost)
+                    .add_system_to_stage(
+                        TickStage::Input,
+                        replay::tick_input.after(replay::drop_stale_recording),
+                    )
+                    .add_system_to_stage(TickStage::Logic, movement)
+                    .add_system_to_stage(TickStage::Logic, attract.after(movement))
+                    .add_system_to_stage(TickStage::Logic, explode.after(attract))
+                    .add_system_to_stage(TickStage::Logic, mass_increase.after(explode))
+                    .add_system_to_stage(TickStage::Logic, check_finish.after(mass_increase))
+                    .add_system_to_stage(TickStage::Logic, pause::timer_fail.after(check_finish))
+                    .add_system_to_stage(TickStage::Logic, ghost::record_ghost.after(check_finish))
+            });
+    }
+}
+
+pub mod harness {
+    use bevy::{
+        asset::AssetPlugin, core::CorePlugin, hierarchy::HierarchyPlugin, scene::ScenePlugin,
+        transform::TransformPlugin,
+    };
+    use bevy_vfx_bag::image::chromatic_aberration::ChromaticAberration;
+
+    use super::*;
+    use crate::{shop::Upgrade, tick::GameClock, Block, GameAssets};
+
+    /// The simulation without a window, a renderer, egui or audio, for the tests and
+    /// the bot on a machine without a GPU. Time doesn't flow on its own, ticks are run
+    /// with [`Sim::run_ticks`].
+    pub fn headless_app() -> App {
+        let mut app = App::new();
+        app.add_plugin(CorePlugin::default())
+            .init_resource::<Time>()
+            .add_plugin(TransformPlugin)
+            .add_plugin(HierarchyPlugin)
+            .add_plugin(AssetPlugin::default())
+            .add_asset::<Mesh>()
+            .add_plugin(ScenePlugin)
+            .init_resource::<Input<MouseButton>>()
+            .insert_resource(ClearColor(Color::BLACK))
+            .insert_resource(ChromaticAberration::default())
+            .insert_resource(GameAssets {
+                lolle: default(),
+                hi: default(),
+                lo: default(),
+                hit: default(),
+                dialogue: default(),
+                tuning: default(),
+                difficulty: default(),
+            })
+            .add_state(GameState::Title)
+            .add_plugin(SimulationPlugin);
+        app
+    }
+
+    /// A headless app and the inputs to play it with.
+    pub struct Sim {
+        pub app: App,
+    }
+
+    impl Sim {
+        pub fn new() -> Self {
+            let mut app = headless_app();
+            app.update();
+            Sim { app }
+        }
+
+        pub fn world(&mut self) -> &mut World {
+            &mut self.app.world
+        }
+
+        pub fn state(&self) -> GameState {
+            self.app
+                .world
+                .resource::<State<GameState>>()
+                .current()
+                .clone()
+        }
+
+        /// Loads a level with a fixed layout and waits for it to be playable.
+        pub fn start_level(&mut self, level: u32, seed: u64) {
+            self.world()
+                .resource_mut::<Info>()
+                .seeds
+                .insert(level, seed);
+            self.world().resource_mut::<Progress>().current_level = level;
+            self.set_state(GameState::LevelLoading);
+            for _ in 0..10 {
+                if self.state() == GameState::Gameplay {
+                    return;
+                }
+                self.app.update();
+            }
+            panic!("the level didn't load, stuck in {:?}", self.state());
+        }
+
+        /// Runs ticks the way the game does, through frames of `frame_time` seconds.
+        #[cfg(test)]
+        pub fn run_frames(&mut self, ticks: u32, frame_time: f32) {
+            let end = self.world().resource::<GameClock>().tick + ticks;
+            // the last frame would run past the end otherwise
+            self.world().resource_mut::<GameClock>().limit = Some(end);
+            while self.world().resource::<GameClock>().tick < end
+                && matches!(self.state(), GameState::Gameplay | GameState::Replay)
+            {
+                let mut time = self.world().resource_mut::<Time>();
+                let last = time.last_update().unwrap_or_else(|| time.startup());
+                time.update_with_instant(last + std::time::Duration::from_secs_f32(frame_time));
+                self.app.update();
+            }
+            self.world().resource_mut::<GameClock>().limit = None;
+        }
+
+        pub fn set_state(&mut self, state: GameState) {
+            self.world()
+                .resource_mut::<State<GameState>>()
+                .set(state)
+                .unwrap();
+            self.app.update();
+        }
+
+        /// Unlocks upgrades as if they were bought in the shop, children after their parent.
+        pub fn unlock(&mut self, unlocks: &[Upgrade]) {
+            let tuning = self.world().resource::<Tuning>().clone();
+            self.world()
+                .resource_scope(|world, mut upgrades: Mut<Upgrades>| {
+                    let mut prog = world.resource_mut::<Progress>();
+                    for upgrade in unlocks {
+                        prog.golden_apples += upgrades.cost(*upgrade, &tuning);
+                        assert!(
+                            upgrades.buy(*upgrade, &mut prog, &tuning),
+                            "{:?} can't be bought",
+                            upgrade
+                        );
+                    }
+                });
+        }
+
+        /// The held inputs, they stay until changed.
+        pub fn actions(&mut self) -> Mut<'_, ActionState> {
+            self.world().resource_mut::<ActionState>()
+        }
+
+        pub fn aim(&mut self) -> Mut<'_, Aim> {
+            self.world().resource_mut::<Aim>()
+        }
+
+        /// Runs ticks the way a replay seeks, as fast as possible and without a clock.
+        pub fn run_ticks(&mut self, ticks: u32) {
+            let mut clock = self.world().resource_mut::<GameClock>();
+            clock.seek = Some(clock.tick + ticks);
+            while self.world().resource::<GameClock>().seek.is_some() {
+                if !matches!(self.state(), GameState::Gameplay | GameState::Replay) {
+                    // the level ended and took the clock with it
+                    self.world().resource_mut::<GameClock>().seek = None;
+                }
+                self.app.update();
+            }
+        }
+
+        /// How far the blocks have been pushed off their bases, summed like the
+        /// level threshold.
+        pub fn score(&mut self) -> f32 {
+            self.world()
+                .query::<(&Block, &Transform)>()
+                .iter(&self.app.world)
+                .map(|(block, tr)| block.rel(tr.translation))
+                .sum()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::harness::Sim;
+    use crate::{
+        aim::Aim,
+        input::{Action, Binding, Bindings},
+        replay::REPLAY_VERSION,
+        save::{self, GameData, SaveData, SaveError, SAVE_VERSION},
+        settings::Settings,
+        shop::{Upgrade, Upgrades},
+        tick::{self, GameClock, TICK},
+        tuning::Tuning,
+        Block, BlockBase, GameState, Info, Level, MainCharacter, Progress, Stats,
+    };
+    use bevy::{ecs::system::SystemState, prelude::*};
+
+    const SEED: u64 = 52;
+
+    fn nearest_block(sim: &mut Sim) -> Vec2 {
+        let start = sim
+            .world()
+            .query_filtered::<&Transform, With<MainCharacter>>()
+            .single(&sim.app.world)
+            .translation
+            .truncate();
+        sim.world()
+            .query::<(&Block, &Transform)>()
+            .iter(&sim.app.world)
+            .filter(|(block, _)| block.density > 0.0)
+            .map(|(_, tr)| tr.translation.truncate() - start)
+            .min_by(|a, b| a.length().total_cmp(&b.length()))
+            .expect("the level has blocks")
+    }
+
+    #[test]
+    fn dash_into_a_cluster_raises_the_score() {
+        let ticks = (2.0 / TICK) as u32;
+
+        let mut idle = Sim::new();
+        idle.start_level(1, SEED);
+        idle.run_ticks(ticks);
+        let idle_score = idle.score();
+
+        let mut sim = Sim::new();
+        sim.unlock(&[Upgrade::Dash]);
+        sim.start_level(1, SEED);
+        let dir = nearest_block(&mut sim).normalize();
+        *sim.aim() = Aim {
+            dir,
+            dash: true,
+            ..default()
+        };
+        sim.actions().movement = dir;
+        sim.actions().press(Action::Dash);
+        sim.run_ticks(ticks);
+
+        assert!(
+            sim.score() > idle_score,
+            "dashing scored {}, standing still {}",
+            sim.score(),
+            idle_score
+        );
+    }
+
+    #[test]
+    fn reaching_the_threshold_advances_current_level() {
+        let mut sim = Sim::new();
+        sim.start_level(1, SEED);
+        for level in sim.world().resource_mut::<Info>().levels.iter_mut() {
+            level.point_threshold = 0.0;
+        }
+        sim.run_ticks(1);
+
+        let prog = sim.world().resource::<Progress>();
+        assert_eq!(prog.current_level, 2);
+        assert!(prog.level_complete);
+        assert_eq!(sim.world().resource::<Stats>().levels_completed, 1);
+
+        // the end of level pause, then the shop
+        sim.run_ticks((3.5 / TICK) as u32);
+        assert_eq!(sim.state(), GameState::Shop);
+    }
+
+    #[test]
+    fn teardown_removes_every_level_entity() {
+        let mut sim = Sim::new();
+        let before = sim.world().entities().len();
+        sim.start_level(1, SEED);
+        sim.run_ticks(10);
+        assert!(sim.world().entities().len() > before);
+
+        sim.set_state(GameState::Title);
+        assert_eq!(sim.world().entities().len(), before);
+        assert_eq!(
+            sim.world().query::<&Block>().iter(&sim.app.world).count(),
+            0
+        );
+        assert_eq!(
+            sim.world()
+                .query::<&BlockBase>()
+                .iter(&sim.app.world)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn loading_a_profile_keeps_the_difficulty_table() {
+        let mut sim = Sim::new();
+        let mut table = sim.world().resource::<Info>().difficulty.clone();
+        for band in table.bands.iter_mut() {
+            band.threshold = 0.5;
+        }
+        sim.world().resource_mut::<Info>().difficulty = table;
+
+        let mut data: SystemState<GameData> = SystemState::new(sim.world());
+        data.get_mut(sim.world()).apply(SaveData::default());
+
+        let mut info = sim.world().resource_mut::<Info>();
+        assert!(info.difficulty.bands.iter().all(|b| b.threshold == 0.5));
+        let level = info.get_level(&Progress::default());
+        let blocks = level.blocks.iter().filter(|b| b.density > 0.0).count();
+        assert_eq!(level.point_threshold, blocks as f32 * 0.5);
+    }
+
+    #[test]
+    fn a_seed_always_builds_the_same_level() {
+        let table = Info::default().difficulty;
+        let level = Level::gen(1, SEED, table.band(1));
+        // the first quarter, the other three are its mirrors
+        let quarter: Vec<_> = level
+            .blocks
+            .iter()
+            .take(5)
+            .map(|b| (b.base_pos.truncate(), b.base_size))
+            .collect();
+        assert_eq!(level.blocks.len(), 20);
+        assert_eq!(
+            quarter,
+            [
+                (Vec2::new(60.0, 72.5), Vec2::new(40.0, 15.0)),
+                (Vec2::new(62.5, 100.0), Vec2::new(35.0, 40.0)),
+                (Vec2::new(47.5, 132.5), Vec2::new(45.0, 25.0)),
+                (Vec2::new(107.5, 67.5), Vec2::new(25.0, 25.0)),
+                (Vec2::new(107.5, 22.5), Vec2::new(15.0, 35.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rebinding_a_key_keeps_the_pad_buttons() {
+        let mut bindings = Bindings::default();
+        bindings.rebind(Action::Dash, Binding::Key(KeyCode::L));
+        assert_eq!(
+            bindings.get(Action::Dash),
+            [
+                Binding::Pad(GamepadButtonType::South),
+                Binding::Pad(GamepadButtonType::RightTrigger),
+                Binding::Key(KeyCode::L),
+            ]
+        );
+        // taken away from the action that had it
+        bindings.rebind(Action::Dash, Binding::Key(KeyCode::K));
+        assert_eq!(bindings.get(Action::Dynamite).len(), 2);
+        assert!(!bindings
+            .get(Action::Dynamite)
+            .contains(&Binding::Key(KeyCode::K)));
+    }
+
+    #[test]
+    fn hard_fail_stops_the_ticks_left_in_the_frame() {
+        let mut sim = Sim::new();
+        sim.world().resource_mut::<Settings>().hard_fail = true;
+        sim.start_level(1, SEED);
+        sim.world()
+            .resource_mut::<Progress>()
+            .level_timer
+            .set_duration(tick::tick_duration());
+        // all in the same frame, like a replay seek
+        sim.run_ticks(10);
+
+        assert_eq!(sim.state(), GameState::Failed);
+        assert_eq!(sim.world().resource::<GameClock>().tick, 1);
+        assert!(!sim.world().resource::<Progress>().level_complete);
+    }
+
+    #[test]
+    fn tuning_out_of_range_is_rejected() {
+        assert!(Tuning::default().validate().is_ok());
+
+        let tuning = Tuning {
+            dash_cost: -5,
+            ..default()
+        };
+        assert!(tuning.validate().is_err());
+
+        let tuning = Tuning {
+            explode_power: f32::NAN,
+            ..default()
+        };
+        assert!(tuning.validate().is_err());
+
+        let mut tuning = Tuning::default();
+        tuning.character.radius = 0.0;
+        assert!(tuning.validate().is_err());
+    }
+
+    #[test]
+    fn difficulty_table_out_of_order_or_range_is_rejected() {
+        let table = Info::default().difficulty;
+        assert!(table.validate().is_ok());
+
+        let mut unsorted = table.clone();
+        unsorted.bands.swap(0, 1);
+        assert!(unsorted.validate().is_err());
+
+        let mut walls_as_blocks = table.clone();
+        walls_as_blocks.bands[0].block_size = (10, 80);
+        assert!(walls_as_blocks.validate().is_err());
+
+        let mut reversed_hue = table;
+        reversed_hue.bands[0].theme.hue = (200.0, 100.0);
+        assert!(reversed_hue.validate().is_err());
+    }
+
+    #[test]
+    fn the_same_inputs_score_the_same_at_any_frame_rate() {
+        let ticks = (2.0 / TICK) as u32;
+        let scores: Vec<f32> = [30.0, 60.0, 144.0]
+            .iter()
+            .map(|fps| {
+                let mut sim = Sim::new();
+                sim.unlock(&[Upgrade::Dash]);
+                sim.start_level(1, SEED);
+                let dir = nearest_block(&mut sim).normalize();
+                *sim.aim() = Aim {
+                    dir,
+                    dash: true,
+                    ..default()
+                };
+                sim.actions().movement = dir;
+                sim.actions().press(Action::Dash);
+                sim.run_frames(ticks, 1.0 / fps);
+                assert_eq!(sim.world().resource::<GameClock>().tick, ticks);
+                sim.score()
+            })
+            .collect();
+        assert_eq!(scores[0], scores[1], "30 and 60 fps");
+        assert_eq!(scores[1], scores[2], "60 and 144 fps");
+    }
+
+    #[test]
+    fn a_first_version_save_is_migrated() {
+        let upgrades = ron::to_string(&Upgrades::default()).unwrap();
+        let v1 = format!(
+            "(version: 1, current_level: 4, golden_apples: 7, upgrades: {}, \
+             level_seeds: {{4: 99}}, best_times: {{3: 12.5}})",
+            upgrades
+        );
+        let data = SaveData::from_text(&v1).expect("a version 1 save loads");
+        assert_eq!(data.current_level, 4);
+        assert_eq!(data.golden_apples, 7);
+        assert_eq!(data.level_seeds.get(&4), Some(&99));
+
+        // written back at the current version
+        let text = ron::to_string(&data).unwrap();
+        assert!(text.starts_with(&format!("(version:{}", SAVE_VERSION)));
+        let again = SaveData::from_text(&text).expect("the migrated save loads");
+        assert_eq!(again.current_level, 4);
+
+        let future = format!("(version: {})", SAVE_VERSION + 1);
+        assert!(matches!(
+            SaveData::from_text(&future),
+            Err(SaveError::Version(v)) if v == SAVE_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn replays_from_another_version_are_refused() {
+        for version in [REPLAY_VERSION - 1, REPLAY_VERSION + 1] {
+            let text = format!("(version: {}, level: 1)", version);
+            assert!(matches!(
+                save::parse_replay(&text),
+                Err(SaveError::Version(v)) if v == version
+            ));
+        }
+    }
+}
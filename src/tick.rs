@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use bevy::{ecs::schedule::ShouldRun, prelude::*, transform::TransformSystem};
+use bevy_rapier2d::prelude::*;
+
+use crate::GameState;
+
+/// Length of a gameplay tick, physics and abilities always advance by exactly this much.
+pub const TICK: f32 = 1.0 / 60.0;
+/// Ticks run in a single frame while seeking through a replay.
+const SEEK_TICKS_PER_FRAME: u32 = 300;
+/// The most the simulation catches up after a long frame, past that it slows down.
+const MAX_FRAME_TIME: f32 = 0.25;
+
+/// The gameplay tick, a schedule run as many times per frame as there are ticks due.
+#[derive(StageLabel)]
+pub struct GameTick;
+
+#[derive(StageLabel)]
+pub enum TickStage {
+    /// Decides what the player does this tick, live or from a replay.
+    Input,
+    /// Abilities and scoring, physics steps right after.
+    Logic,
+}
+
+#[derive(Resource)]
+pub struct GameClock {
+    accumulator: f32,
+    looping: bool,
+    burst: u32,
+    /// Ticks since the level started.
+    pub tick: u32,
+    /// No ticks past this one, the end of a replay.
+    pub limit: Option<u32>,
+    /// Run ticks as fast as possible until this one.
+    pub seek: Option<u32>,
+    pub speed: f32,
+    pub paused: bool,
+    /// No more ticks until the level starts again, the state changes after the frame and
+    /// the ticks left in it would keep playing.
+    pub halted: bool,
+}
+
+impl GameClock {
+    /// How far the frame is between the last two ticks, to smooth out their positions.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / TICK).clamp(0.0, 1.0)
+    }
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        Self {
+            accumulator: 0.0,
+            looping: false,
+            burst: 0,
+            tick: 0,
+            limit: None,
+            seek: None,
+            speed: 1.0,
+            paused: false,
+            halted: false,
+        }
+    }
+}
+
+pub fn tick_duration() -> Duration {
+    Duration::from_secs_f32(TICK)
+}
+
+/// Where a body was before the last tick, its sprite is drawn in between.
+#[derive(Component)]
+pub struct Interpolated {
+    previous: Transform,
+}
+
+impl Interpolated {
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            previous: transform,
+        }
+    }
+}
+
+/// The sprite of an [`Interpolated`] body, a child so that the body's own transform,
+/// which Rapier owns, is never touched.
+#[derive(Component)]
+pub struct Smoothed;
+
+fn store_previous(mut bodies: Query<(&mut Interpolated, &Transform)>) {
+    for (mut interpolated, tr) in bodies.iter_mut() {
+        interpolated.previous = *tr;
+    }
+}
+
+fn interpolate(
+    clock: Res<GameClock>,
+    bodies: Query<(&Interpolated, &Transform, &Children)>,
+    mut sprites: Query<&mut Transform, (With<Smoothed>, Without<Interpolated>)>,
+) {
+    let alpha = clock.alpha();
+    for (interpolated, tr, children) in bodies.iter() {
+        let translation = interpolated
+            .previous
+            .translation
+            .lerp(tr.translation, alpha);
+        let rotation = interpolated.previous.rotation.slerp(tr.rotation, alpha);
+        let inverse = tr.rotation.inverse();
+        let mut iter = sprites.iter_many_mut(children.iter());
+        while let Some(mut sprite) = iter.fetch_next() {
+            sprite.translation = inverse * (translation - tr.translation);
+            sprite.rotation = inverse * rotation;
+        }
+    }
+}
+
+pub fn reset_clock(mut clock: ResMut<GameClock>) {
+    clock.tick = 0;
+    clock.accumulator = 0.0;
+    clock.looping = false;
+    clock.halted = false;
+}
+
+fn run_ticks(
+    time: Res<Time>,
+    mut clock: ResMut<GameClock>,
+    state: Res<State<GameState>>,
+) -> ShouldRun {
+    if !matches!(state.current(), GameState::Gameplay | GameState::Replay) {
+        clock.looping = false;
+        return ShouldRun::No;
+    }
+    let at_limit = clock.limit.is_some_and(|limit| clock.tick >= limit);
+    if at_limit || clock.seek.is_some_and(|seek| clock.tick >= seek) {
+        clock.seek = None;
+    }
+    if at_limit || clock.halted || (clock.paused && clock.seek.is_none()) {
+        clock.looping = false;
+        return ShouldRun::No;
+    }
+    if !clock.looping {
+        // first check of the frame, the ticks due are added up once
+        clock.burst = 0;
+        let speed = clock.speed;
+        clock.accumulator =
+            (clock.accumulator + time.delta_seconds() * speed).min(MAX_FRAME_TIME * speed.max(1.0));
+    }
+    let run = if clock.seek.is_some() {
+        clock.burst < SEEK_TICKS_PER_FRAME
+    } else if clock.accumulator >= TICK {
+        clock.accumulator -= TICK;
+        true
+    } else {
+        false
+    };
+    clock.looping = run;
+    if run {
+        clock.burst += 1;
+        ShouldRun::YesAndCheckAgain
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Runs the gameplay and Rapier on the fixed tick instead of once per frame.
+pub struct TickPlugin;
+
+impl Plugin for TickPlugin {
+    fn build(&self, app: &mut App) {
+        let physics = |stage| {
+            SystemStage::parallel()
+                .with_system_set(RapierPhysicsPlugin::<NoUserData>::get_systems(stage))
+        };
+        app.init_resource::<GameClock>()
+            .add_stage_after(
+                CoreStage::Update,
+                GameTick,
+                Schedule::default()
+                    .with_run_criteria(run_ticks)
+                    .with_stage(
+                        TickStage::Input,
+                        SystemStage::single_threaded().with_system(store_previous),
+                    )
+                    .with_stage_after(TickStage::Input, TickStage::Logic, SystemStage::parallel())
+                    .with_stage_after(
+                        TickStage::Logic,
+                        PhysicsStages::SyncBackend,
+                        physics(PhysicsStages::SyncBackend),
+                    )
+                    .with_stage_after(
+                        PhysicsStages::SyncBackend,
+                        PhysicsStages::StepSimulation,
+                        physics(PhysicsStages::StepSimulation),
+                    )
+                    .with_stage_after(
+                        PhysicsStages::StepSimulation,
+                        PhysicsStages::Writeback,
+                        physics(PhysicsStages::Writeback),
+                    ),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                interpolate.before(TransformSystem::TransformPropagate),
+            )
+            // despawns are caught every frame, they don't wait for a tick
+            .add_stage_before(
+                CoreStage::Last,
+                PhysicsStages::DetectDespawn,
+                physics(PhysicsStages::DetectDespawn),
+            );
+    }
+}
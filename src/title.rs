@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::{menu::MenuNav, profiles::Profiles, save::GameData, settings::SettingsMenu, GameState};
+
+#[derive(Resource, Default)]
+pub struct TitleMenu {
+    /// New game is waiting for the player to confirm wiping their progress.
+    confirm_new: bool,
+    pub replays_open: bool,
+    error: Option<String>,
+}
+
+pub fn title_screen(
+    mut egui_context: ResMut<EguiContext>,
+    mut title: ResMut<TitleMenu>,
+    mut profiles: ResMut<Profiles>,
+    mut settings_menu: ResMut<SettingsMenu>,
+    mut data: GameData,
+    mut state: ResMut<State<GameState>>,
+    mut nav: ResMut<MenuNav>,
+) {
+    if settings_menu.open || profiles.menu_open || title.replays_open {
+        return;
+    }
+    let progress = profiles
+        .active_save()
+        .map(|s| (s.current_level, s.golden_apples));
+    egui::Window::new("Title")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::splat(0.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.heading("Seeding Disorder");
+            if let Some(name) = &profiles.active {
+                ui.label(format!("Playing as {}", name));
+            }
+            let continue_text = match progress {
+                Some((level, _)) => format!("Continue (level {})", level),
+                None => "Continue".to_string(),
+            };
+            if nav.button(ui, progress.is_some(), continue_text) {
+                state.set(GameState::LevelLoading).ok();
+            }
+            let started = progress.is_some_and(|(level, apples)| level > 1 || apples > 0);
+            if title.confirm_new {
+                ui.label("This wipes the levels and upgrades of this profile");
+                if nav.button(ui, true, "Really start over") {
+                    title.confirm_new = false;
+                    data.new_game();
+                    state.set(GameState::Intro).ok();
+                }
+                if nav.button(ui, true, "Cancel") {
+                    title.confirm_new = false;
+                }
+            } else if nav.button(ui, true, "New game") {
+                if started {
+                    title.confirm_new = true;
+                } else {
+                    match profiles.ensure_active(&mut data) {
+                        Ok(()) => {
+                            data.new_game();
+                            state.set(GameState::Intro).ok();
+                        }
+                        Err(e) => title.error = Some(e),
+                    }
+                }
+            }
+            if nav.button(ui, progress.is_some(), "Replays") {
+                title.replays_open = true;
+            }
+            if nav.button(ui, true, "Profiles") {
+                profiles.menu_open = true;
+            }
+            if nav.button(ui, true, "Settings") {
+                settings_menu.open = true;
+            }
+            if let Some(error) = &title.error {
+                ui.label(error);
+            }
+        });
+}
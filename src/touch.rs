@@ -0,0 +1,213 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext, EguiSettings};
+
+use crate::{
+    camera,
+    input::{Action, ActionState},
+    shop::{Upgrade, Upgrades},
+};
+
+const JOYSTICK_RADIUS: f32 = 60.0;
+const BUTTON_RADIUS: f32 = 35.0;
+/// How far a button has to be dragged before it aims, a plain tap keeps the cursor aim.
+const AIM_DEAD_ZONE: f32 = 15.0;
+
+/// On screen controls, laid out from the bottom right corner of the window.
+const BUTTONS: [(Action, Upgrade, Vec2); 3] = [
+    (Action::Dash, Upgrade::Dash, Vec2::new(70.0, 70.0)),
+    (Action::Magnet, Upgrade::Magnet, Vec2::new(160.0, 60.0)),
+    (Action::Dynamite, Upgrade::Dynamite, Vec2::new(70.0, 160.0)),
+];
+
+struct Joystick {
+    id: u64,
+    origin: Vec2,
+    current: Vec2,
+}
+
+/// A finger that went down on a button, the action stays pressed until it's lifted.
+struct ButtonTouch {
+    id: u64,
+    action: Action,
+    center: Vec2,
+    current: Vec2,
+}
+
+impl ButtonTouch {
+    fn drag(&self) -> Vec2 {
+        self.current - self.center
+    }
+}
+
+/// The virtual joystick and ability buttons, all positions are in window
+/// coordinates with the origin at the bottom left, like the cursor.
+#[derive(Resource, Default)]
+pub struct TouchControls {
+    /// Shown once the player touches the screen for the first time.
+    enabled: bool,
+    joystick: Option<Joystick>,
+    buttons: Vec<ButtonTouch>,
+    pressed: HashSet<Action>,
+}
+
+impl TouchControls {
+    fn movement(&self) -> Vec2 {
+        self.joystick.as_ref().map_or(Vec2::ZERO, |j| {
+            ((j.current - j.origin) / JOYSTICK_RADIUS).clamp_length_max(1.0)
+        })
+    }
+}
+
+fn button_center(window: &Window, corner_offset: Vec2) -> Vec2 {
+    Vec2::new(window.width() - corner_offset.x, corner_offset.y)
+}
+
+/// Touches keep winit's origin at the top left on every backend, while the cursor is moved
+/// to the bottom left against the physical height of the window. Touches go the same way.
+fn touch_to_window(window: &Window, position: Vec2) -> Vec2 {
+    let scale = window.scale_factor() as f32;
+    let y = window.physical_height() as f32 - position.y * scale;
+    Vec2::new(position.x, y / scale)
+}
+
+pub fn touch_controls(
+    touches: Res<Touches>,
+    windows: Res<Windows>,
+    upgrades: Res<Upgrades>,
+    mut controls: ResMut<TouchControls>,
+    mut actions: ResMut<ActionState>,
+) {
+    let window = if let Some(window) = windows.get_primary() {
+        window
+    } else {
+        return;
+    };
+    let controls = &mut *controls;
+    if touches.iter().next().is_some() {
+        controls.enabled = true;
+    }
+
+    if let Some(joystick) = &mut controls.joystick {
+        if let Some(touch) = touches.get_pressed(joystick.id) {
+            joystick.current = touch_to_window(window, touch.position());
+        } else {
+            controls.joystick = None;
+        }
+    }
+
+    controls.buttons.retain_mut(|button| {
+        if let Some(touch) = touches.get_pressed(button.id) {
+            button.current = touch_to_window(window, touch.position());
+            true
+        } else {
+            false
+        }
+    });
+
+    for touch in touches.iter_just_pressed() {
+        let pos = touch_to_window(window, touch.position());
+        let button = BUTTONS.iter().find(|(_, upgrade, offset)| {
+            let center = button_center(window, *offset);
+            upgrades.has(*upgrade) && pos.distance(center) < BUTTON_RADIUS
+        });
+        if let Some((action, _, offset)) = button {
+            controls.buttons.push(ButtonTouch {
+                id: touch.id(),
+                action: *action,
+                center: button_center(window, *offset),
+                current: pos,
+            });
+        } else if controls.joystick.is_none() && pos.x < window.width() * 0.5 {
+            controls.joystick = Some(Joystick {
+                id: touch.id(),
+                origin: pos,
+                current: pos,
+            });
+        }
+    }
+
+    controls.pressed = controls.buttons.iter().map(|b| b.action).collect();
+    for action in controls.pressed.iter() {
+        actions.press(*action);
+    }
+    actions.movement = (actions.movement + controls.movement()).clamp_length_max(1.0);
+
+    // the last button dragged aims, through the camera like the cursor
+    let dragged = controls
+        .buttons
+        .iter()
+        .rev()
+        .find(|b| b.drag().length() > AIM_DEAD_ZONE);
+    if let Some(button) = dragged {
+        let from = camera::window_to_world(window, button.center);
+        let to = camera::window_to_world(window, button.current);
+        actions.aim = (to - from).normalize_or_zero();
+    }
+}
+
+pub fn draw_touch_controls(
+    mut egui_context: ResMut<EguiContext>,
+    egui_settings: Res<EguiSettings>,
+    windows: Res<Windows>,
+    upgrades: Res<Upgrades>,
+    controls: Res<TouchControls>,
+) {
+    if !controls.enabled {
+        return;
+    }
+    let window = if let Some(window) = windows.get_primary() {
+        window
+    } else {
+        return;
+    };
+    let scale = egui_settings.scale_factor as f32;
+    let to_egui = |pos: Vec2| egui::pos2(pos.x / scale, (window.height() - pos.y) / scale);
+    let painter = egui_context.ctx_mut().layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("TouchControls"),
+    ));
+    let fill = egui::Color32::from_rgba_unmultiplied(255, 255, 255, 30);
+    let stroke = egui::Stroke::new(1.0, egui::Color32::WHITE);
+
+    if let Some(joystick) = &controls.joystick {
+        painter.circle(
+            to_egui(joystick.origin),
+            JOYSTICK_RADIUS / scale,
+            fill,
+            stroke,
+        );
+        let knob = joystick.origin + controls.movement() * JOYSTICK_RADIUS;
+        painter.circle_filled(
+            to_egui(knob),
+            BUTTON_RADIUS * 0.5 / scale,
+            egui::Color32::WHITE,
+        );
+    }
+    for (action, upgrade, offset) in BUTTONS.iter() {
+        if !upgrades.has(*upgrade) {
+            continue;
+        }
+        let center = to_egui(button_center(window, *offset));
+        let fill = if controls.pressed.contains(action) {
+            egui::Color32::from_rgba_unmultiplied(255, 255, 255, 120)
+        } else {
+            fill
+        };
+        painter.circle(center, BUTTON_RADIUS / scale, fill, stroke);
+        painter.text(
+            center,
+            egui::Align2::CENTER_CENTER,
+            action.name(),
+            egui::FontId::proportional(8.0),
+            egui::Color32::WHITE,
+        );
+    }
+    for button in controls.buttons.iter() {
+        if button.drag().length() > AIM_DEAD_ZONE {
+            let knob = button.center + button.drag().clamp_length_max(BUTTON_RADIUS);
+            painter.line_segment([to_egui(button.center), to_egui(knob)], stroke);
+        }
+    }
+}
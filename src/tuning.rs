@@ -0,0 +1,245 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use crate::replay::ReplayViewer;
+
+/// Where the tuning lives in the assets folder, it's written back there.
+const TUNING_FILE: &str = "gameplay.tuning.ron";
+/// Smallest character radius the inspector goes down to.
+const MIN_RADIUS: f32 = 1.0;
+/// Most golden apples an ability can cost.
+const MAX_COST: i32 = 99;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CharacterTuning {
+    pub radius: f32,
+    pub restitution: f32,
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    /// Before upgrades.
+    pub dash_cooldown: f32,
+    pub attract_cooldown: f32,
+    pub explode_cooldown: f32,
+}
+
+/// The numbers behind the abilities and the character, from `assets/gameplay.tuning.ron`.
+#[derive(Resource, Clone, Serialize, Deserialize, TypeUuid)]
+#[uuid = "c3a1e8f2-6b5d-4e0a-9f47-1d2b8c6e3a90"]
+pub struct Tuning {
+    /// Velocity gained per second of movement.
+    pub acceleration: f32,
+    pub dash_boost: f32,
+    pub dash_cost: i32,
+    /// Pull per second while the magnet is held.
+    pub attract_power: f32,
+    /// Reach of the magnet before upgrades, the aimed beam goes twice as far.
+    pub attract_radius: f32,
+    pub attract_cost: i32,
+    /// A single kick, it doesn't depend on the tick length.
+    pub explode_power: f32,
+    /// Reach of the dynamite before upgrades, the aimed cone goes half again as far.
+    pub explode_radius: f32,
+    pub explode_cost: i32,
+    pub character: CharacterTuning,
+}
+
+impl Default for Tuning {
+    /// The tuning shipped with the game, for when the asset isn't loaded, like headless.
+    fn default() -> Self {
+        ron::from_str(include_str!("../assets/gameplay.tuning.ron"))
+            .expect("the shipped tuning is valid")
+    }
+}
+
+impl Tuning {
+    /// The same ranges as the inspector, anything else would break the game or the shop,
+    /// so it's caught when the tuning loads.
+    pub fn validate(&self) -> Result<(), String> {
+        let c = &self.character;
+        let numbers = [
+            ("acceleration", self.acceleration, 0.0),
+            ("dash_boost", self.dash_boost, 0.0),
+            ("attract_power", self.attract_power, 0.0),
+            ("attract_radius", self.attract_radius, 0.0),
+            ("explode_power", self.explode_power, 0.0),
+            ("explode_radius", self.explode_radius, 0.0),
+            ("character.radius", c.radius, MIN_RADIUS),
+            ("character.restitution", c.restitution, 0.0),
+            ("character.linear_damping", c.linear_damping, 0.0),
+            ("character.angular_damping", c.angular_damping, 0.0),
+            ("character.dash_cooldown", c.dash_cooldown, 0.0),
+            ("character.attract_cooldown", c.attract_cooldown, 0.0),
+            ("character.explode_cooldown", c.explode_cooldown, 0.0),
+        ];
+        for (name, value, min) in numbers {
+            if !value.is_finite() || value < min {
+                return Err(format!("{} has to be a number from {} up", name, min));
+            }
+        }
+        let costs = [
+            ("dash_cost", self.dash_cost),
+            ("attract_cost", self.attract_cost),
+            ("explode_cost", self.explode_cost),
+        ];
+        for (name, cost) in costs {
+            if !(0..=MAX_COST).contains(&cost) {
+                return Err(format!("{} goes from 0 to {}", name, MAX_COST));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct TuningLoader;
+
+impl AssetLoader for TuningLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let tuning = ron::de::from_bytes::<Tuning>(bytes)?;
+            tuning.validate().map_err(bevy::asset::Error::msg)?;
+            load_context.set_default_asset(LoadedAsset::new(tuning));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tuning.ron"]
+    }
+}
+
+/// Takes the tuning in use from the asset when it's loaded and whenever the file changes.
+/// While a replay plays with its own tuning, the new one waits for the replay to end.
+pub fn apply_tuning(
+    mut events: EventReader<AssetEvent<Tuning>>,
+    assets: Res<Assets<Tuning>>,
+    mut tuning: ResMut<Tuning>,
+    mut viewer: ResMut<ReplayViewer>,
+) {
+    for event in events.iter() {
+        if let AssetEvent::Created { handle } | AssetEvent::Modified { handle } = event {
+            if let Some(loaded) = assets.get(handle) {
+                info!("gameplay tuning reloaded");
+                match &mut viewer.tuning {
+                    Some(stash) => *stash = loaded.clone(),
+                    None => *tuning = loaded.clone(),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct TuningInspector {
+    open: bool,
+    status: Option<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_tuning(tuning: &Tuning) -> Result<(), String> {
+    let text = ron::ser::to_string_pretty(tuning, ron::ser::PrettyConfig::default())
+        .map_err(|e| e.to_string())?;
+    let path = bevy::asset::FileAssetIo::get_base_path()
+        .join("assets")
+        .join(TUNING_FILE);
+    // ron doesn't keep comments, the ones heading the file are put back
+    let header: String = std::fs::read_to_string(&path)
+        .unwrap_or_default()
+        .lines()
+        .take_while(|line| line.starts_with("//"))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    std::fs::write(path, header + &text).map_err(|e| e.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_tuning(_tuning: &Tuning) -> Result<(), String> {
+    Err("the assets can't be written from the browser".to_string())
+}
+
+/// F2 shows the tuning to tweak it live, the character changes from the next level.
+pub fn tuning_inspector(
+    mut egui_context: ResMut<EguiContext>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut inspector: ResMut<TuningInspector>,
+    mut tuning: ResMut<Tuning>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F2) {
+        inspector.open = !inspector.open;
+    }
+    if !inspector.open {
+        return;
+    }
+    egui::Window::new("Tuning")
+        .title_bar(false)
+        .resizable(false)
+        .anchor(egui::Align2::LEFT_TOP, egui::Vec2::splat(0.0))
+        .show(egui_context.ctx_mut(), |ui| {
+            let tuning = &mut *tuning;
+            egui::Grid::new("Tuning").show(ui, |ui| {
+                let mut row = |name: &str, value: &mut f32, speed: f64, min: f32| {
+                    ui.label(name);
+                    ui.add(
+                        egui::DragValue::new(value)
+                            .speed(speed)
+                            .clamp_range(min..=f32::MAX),
+                    );
+                    ui.end_row();
+                };
+                row("Acceleration", &mut tuning.acceleration, 10.0, 0.0);
+                row("Dash boost", &mut tuning.dash_boost, 0.05, 0.0);
+                row("Magnet power", &mut tuning.attract_power, 100.0, 0.0);
+                row("Magnet radius", &mut tuning.attract_radius, 1.0, 0.0);
+                row("Dynamite power", &mut tuning.explode_power, 100.0, 0.0);
+                row("Dynamite radius", &mut tuning.explode_radius, 1.0, 0.0);
+                let character = &mut tuning.character;
+                row("Radius", &mut character.radius, 0.5, MIN_RADIUS);
+                row("Restitution", &mut character.restitution, 0.01, 0.0);
+                row("Linear damping", &mut character.linear_damping, 0.05, 0.0);
+                row("Angular damping", &mut character.angular_damping, 0.05, 0.0);
+                row("Dash cooldown", &mut character.dash_cooldown, 0.01, 0.0);
+                row(
+                    "Magnet cooldown",
+                    &mut character.attract_cooldown,
+                    0.01,
+                    0.0,
+                );
+                row(
+                    "Dynamite cooldown",
+                    &mut character.explode_cooldown,
+                    0.01,
+                    0.0,
+                );
+            });
+            egui::Grid::new("TuningCosts").show(ui, |ui| {
+                for (name, cost) in [
+                    ("Dash cost", &mut tuning.dash_cost),
+                    ("Magnet cost", &mut tuning.attract_cost),
+                    ("Dynamite cost", &mut tuning.explode_cost),
+                ] {
+                    ui.label(name);
+                    ui.add(egui::DragValue::new(cost).clamp_range(0..=MAX_COST));
+                    ui.end_row();
+                }
+            });
+            if ui.button("Write to disk").clicked() {
+                inspector.status = Some(match write_tuning(tuning) {
+                    Ok(()) => format!("Saved to assets/{}", TUNING_FILE),
+                    Err(e) => format!("Could not save the tuning, {}", e),
+                });
+            }
+            if let Some(status) = &inspector.status {
+                ui.label(status);
+            }
+        });
+}